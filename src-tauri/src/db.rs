@@ -1,15 +1,134 @@
-use rusqlite::{params, Connection, Result};
-use serde::Deserialize;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::fs;
-use std::sync::Mutex;
-use tauri::Manager;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::{Emitter, Manager};
 
-pub struct DbState(pub Mutex<Connection>);
+use crate::epub::{
+    covers_dir, extract_chapters, extract_epub_metadata, get_epub_spine, get_epub_toc,
+    remove_cached_cover,
+};
+use crate::error::AppError;
+use crate::mobi::extract_mobi_metadata;
+use crate::pdf::extract_pages;
+use crate::utils::{
+    fold_diacritics, hash_file, lemmas, levenshtein, sniff_format, FileFormat, HashAlgorithm,
+};
 
-#[derive(Deserialize)]
-struct DictionaryEntry {
-    word: String,
-    definition: String,
+pub type DbPool = Pool<SqliteConnectionManager>;
+
+/// Connection pool shared by all commands; each command checks out its own connection
+pub struct DbState(pub DbPool);
+
+/// Set once `DbState` is managed. The `db-ready` event can fire before the page
+/// has subscribed to it, so the frontend polls `is_db_ready` as well.
+#[derive(Default)]
+pub struct DbReady(pub AtomicBool);
+
+/// Whether the database is initialized and commands using it can be called
+#[tauri::command]
+pub fn is_db_ready(state: tauri::State<DbReady>) -> bool {
+    state.0.load(Ordering::SeqCst)
+}
+
+/// Upper bound on pooled connections to the on-disk database
+const MAX_POOL_SIZE: u32 = 8;
+
+/// Source name given to the bundled entries and to rows from before sources existed
+const BUNDLED_DICTIONARY_SOURCE: &str = "english";
+/// Source name of definitions written in the app with `add_entry`
+const USER_DICTIONARY_SOURCE: &str = "user";
+
+/// Columns of the `dictionary` FTS5 table. Everything but the word and its
+/// definition is unindexed so it never matches a search.
+const DICTIONARY_FTS_COLUMNS: &str =
+    "word, definition, source UNINDEXED, part_of_speech UNINDEXED, ipa UNINDEXED";
+/// Tokenizer of the `dictionary` table, folding case and diacritics so "cafe"
+/// finds "café"
+const DICTIONARY_FTS_TOKENIZER: &str = "tokenize = 'unicode61 remove_diacritics 2'";
+
+/// A dictionary definition; `part_of_speech` and `ipa` are `null` when the
+/// source dictionary doesn't provide them
+#[derive(Serialize, Deserialize)]
+pub struct DictionaryEntry {
+    pub word: String,
+    pub definition: String,
+    #[serde(default)]
+    pub part_of_speech: Option<String>,
+    #[serde(default)]
+    pub ipa: Option<String>,
+}
+
+/// A book in the library; timestamps are Unix seconds
+#[derive(Serialize, Deserialize)]
+pub struct Book {
+    pub id: i64,
+    pub title: String,
+    pub author: Option<String>,
+    pub file_path: String,
+    pub file_hash: Option<String>,
+    pub added_at: i64,
+    pub last_opened: Option<i64>,
+    /// When the book was marked as finished, `None` while it's unfinished
+    pub finished_at: Option<i64>,
+    /// When the book was moved to the trash, `None` while it's in the library
+    pub deleted_at: Option<i64>,
+}
+
+/// Last reading position in a book; `location` is a CFI or page number and
+/// `percent` the fraction of the book read, from 0.0 to 1.0
+#[derive(Serialize, Deserialize)]
+pub struct Progress {
+    pub book_id: i64,
+    pub location: String,
+    pub percent: f64,
+    pub updated_at: i64,
+}
+
+/// Per-book display settings; a `None` field means the global default applies
+#[derive(Serialize, Deserialize)]
+pub struct BookSettings {
+    pub font_family: Option<String>,
+    pub font_size: Option<f64>,
+    pub theme: Option<String>,
+    pub line_height: Option<f64>,
+    pub margin: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Bookmark {
+    pub id: i64,
+    pub book_id: i64,
+    pub location: String,
+    pub label: Option<String>,
+    pub note: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Highlight {
+    pub id: i64,
+    pub book_id: i64,
+    pub location: String,
+    pub text: String,
+    pub color: String,
+    pub note: Option<String>,
+    pub created_at: i64,
+}
+
+/// A hit from the library full-text search
+#[derive(Serialize)]
+pub struct ContentMatch {
+    pub book_id: i64,
+    pub chapter_href: String,
+    /// Text around the match with the matched terms wrapped in `<b>` tags
+    pub snippet: String,
 }
 
 #[derive(Deserialize)]
@@ -18,51 +137,604 @@ struct DictionaryData {
 }
 
 /// Initialize the database - loads from bundled dictionary.json
-pub fn init_db(app_handle: Option<&tauri::AppHandle>) -> Result<Connection> {
-    // Use persistent database in app data directory if available, otherwise in-memory
-    let conn = if let Some(handle) = app_handle {
-        if let Some(app_dir) = handle.path().app_data_dir().ok() {
-            let _ = fs::create_dir_all(&app_dir);
-            let db_path = app_dir.join("dictionary.db");
-            Connection::open(&db_path)?
-        } else {
-            Connection::open_in_memory()?
-        }
-    } else {
-        Connection::open_in_memory()?
-    };
+///
+/// With an app handle the database lives in `dictionary.db` under the app data
+/// directory so it survives restarts; without one (e.g. in tests) it is kept in memory.
+pub fn init_db(app_handle: Option<&tauri::AppHandle>) -> Result<DbPool, AppError> {
+    let path = app_handle.and_then(db_path);
+    let in_memory = path.is_none();
+    let manager = match path {
+        Some(path) => SqliteConnectionManager::file(path),
+        None => SqliteConnectionManager::memory(),
+    }
+    // SQLite leaves foreign keys off unless each connection turns them on
+    .with_init(|conn| {
+        conn.execute_batch("PRAGMA busy_timeout = 5000; PRAGMA foreign_keys = ON;")?;
+        register_functions(conn)
+    });
+
+    // Every in-memory connection would be a separate empty database
+    let max_size = if in_memory { 1 } else { MAX_POOL_SIZE };
+    let pool = Pool::builder().max_size(max_size).build(manager)?;
+
+    let conn = pool.get()?;
+    init_schema(&conn, app_handle)?;
+
+    Ok(pool)
+}
+
+/// Bring the schema up to date and seed the dictionary on first launch
+fn init_schema(conn: &Connection, app_handle: Option<&tauri::AppHandle>) -> Result<()> {
+    // WAL lets pooled readers proceed while another connection writes. The journal
+    // mode can't change inside a transaction, so it is set before any migration.
+    conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+    run_migrations(conn)?;
+
+    // Only seed on first launch so repeated startups stay cheap
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM dictionary", [], |row| row.get(0))?;
+
+    if count == 0 {
+        // Load dictionary from bundled resource or embedded data
+        seed_dictionary(conn, &seed_entries(app_handle))?;
+    }
+
+    Ok(())
+}
+
+/// One step of schema evolution, applied at most once per database
+type Migration = fn(&Connection) -> Result<()>;
+
+/// Schema changes in the order they were introduced. Never edit or reorder a
+/// released step; add a new one to the end instead. Databases created before
+/// versioning (user_version 0) may already contain any of the objects from the
+/// first steps, which is why those use `IF NOT EXISTS`.
+const MIGRATIONS: &[Migration] = &[
+    create_dictionary,
+    create_library,
+    create_reading_state,
+    create_annotations,
+    create_content_index,
+    create_vocabulary,
+    create_preferences,
+    create_tags,
+    cascade_book_deletes,
+    add_bookmark_notes,
+    add_finished_flag,
+    fold_dictionary_diacritics,
+    create_collections,
+    create_hash_cache,
+    add_book_trash,
+    create_reading_goals,
+];
+
+/// Apply the migrations newer than the database's `PRAGMA user_version`, each in
+/// its own transaction together with the version bump
+fn run_migrations(conn: &Connection) -> Result<()> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version as usize > MIGRATIONS.len() {
+        eprintln!(
+            "Database schema version {} is newer than this build knows ({})",
+            version,
+            MIGRATIONS.len()
+        );
+        return Ok(());
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let tx = conn.unchecked_transaction()?;
+        migration(&tx)?;
+        tx.pragma_update(None, "user_version", (index + 1) as i64)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Migration 1: the full-text dictionary
+fn create_dictionary(conn: &Connection) -> Result<()> {
+    // Older builds kept the dictionary in a plain table holding nothing but seed
+    // data, so it is safe to drop it and let the FTS5 table below be re-seeded.
+    let legacy_table: bool = conn.query_row(
+        "SELECT EXISTS(
+            SELECT 1 FROM sqlite_master
+            WHERE type = 'table' AND name = 'dictionary' AND sql NOT LIKE 'CREATE VIRTUAL TABLE%'
+        )",
+        [],
+        |row| row.get(0),
+    )?;
+    if legacy_table {
+        conn.execute("DROP TABLE dictionary", [])?;
+    }
+
+    // Create the full-text table if it doesn't exist. Only the word and its
+    // definition are searchable; `source` names the dictionary an entry came from.
+    conn.execute(
+        &format!(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS dictionary USING fts5({})",
+            DICTIONARY_FTS_COLUMNS
+        ),
+        [],
+    )?;
+    migrate_dictionary_columns(conn)?;
+
+    Ok(())
+}
 
-    // Create tables if they don't exist
+/// Migration 2: the book library
+fn create_library(conn: &Connection) -> Result<()> {
+    // Library of books the user has added
     conn.execute(
-        "CREATE TABLE IF NOT EXISTS dictionary (
+        "CREATE TABLE IF NOT EXISTS books (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
-            word TEXT NOT NULL COLLATE NOCASE,
-            definition TEXT NOT NULL
+            title TEXT NOT NULL,
+            author TEXT,
+            file_path TEXT NOT NULL,
+            file_hash TEXT,
+            added_at INTEGER NOT NULL DEFAULT (unixepoch()),
+            last_opened INTEGER
         )",
         [],
     )?;
 
-    // Create index for faster lookups
+    // The same file may only appear once in the library
     conn.execute(
-        "CREATE INDEX IF NOT EXISTS idx_word ON dictionary(word COLLATE NOCASE)",
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_books_file_hash ON books(file_hash)",
         [],
     )?;
 
-    // Check if dictionary is already populated
-    let count: i64 = conn.query_row("SELECT COUNT(*) FROM dictionary", [], |row| row.get(0))?;
+    Ok(())
+}
 
-    if count == 0 {
-        // Load dictionary from bundled resource or embedded data
-        load_dictionary_data(&conn, app_handle)?;
+/// Migration 3: reading progress, sessions and per-book display settings
+fn create_reading_state(conn: &Connection) -> Result<()> {
+    // Where the reader left off in each book
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS reading_progress (
+            book_id INTEGER PRIMARY KEY REFERENCES books(id),
+            location TEXT NOT NULL,
+            percent REAL NOT NULL,
+            updated_at INTEGER NOT NULL DEFAULT (unixepoch())
+        )",
+        [],
+    )?;
+
+    // Timed reading sessions; `ended_at` stays NULL while a session is open
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS reading_sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            book_id INTEGER NOT NULL REFERENCES books(id),
+            started_at INTEGER NOT NULL DEFAULT (unixepoch()),
+            ended_at INTEGER,
+            words_read INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX IF NOT EXISTS idx_reading_sessions_book ON reading_sessions(book_id);",
+    )?;
+
+    // Display settings remembered for each book
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS book_settings (
+            book_id INTEGER PRIMARY KEY REFERENCES books(id),
+            font_family TEXT,
+            font_size REAL,
+            theme TEXT,
+            line_height REAL,
+            margin REAL,
+            updated_at INTEGER NOT NULL DEFAULT (unixepoch())
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Migration 4: bookmarks and highlights
+fn create_annotations(conn: &Connection) -> Result<()> {
+    // User-placed bookmarks; the label is optional
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS bookmarks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            book_id INTEGER NOT NULL REFERENCES books(id),
+            location TEXT NOT NULL,
+            label TEXT,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch())
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_bookmarks_book ON bookmarks(book_id)",
+        [],
+    )?;
+
+    // Highlighted passages, with an FTS5 index over text and note kept in sync by triggers
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS highlights (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            book_id INTEGER NOT NULL REFERENCES books(id),
+            location TEXT NOT NULL,
+            text TEXT NOT NULL,
+            color TEXT NOT NULL,
+            note TEXT,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+        CREATE INDEX IF NOT EXISTS idx_highlights_book ON highlights(book_id);
+        CREATE VIRTUAL TABLE IF NOT EXISTS highlights_fts USING fts5(
+            text, note, content = 'highlights', content_rowid = 'id'
+        );",
+    )?;
+    conn.execute_batch(HIGHLIGHTS_FTS_TRIGGERS)?;
+
+    Ok(())
+}
+
+/// Keep `highlights_fts` in step with `highlights`. Dropping the table drops
+/// these too, so they are recreated whenever the table is rebuilt.
+const HIGHLIGHTS_FTS_TRIGGERS: &str =
+    "CREATE TRIGGER IF NOT EXISTS highlights_ai AFTER INSERT ON highlights BEGIN
+        INSERT INTO highlights_fts (rowid, text, note) VALUES (new.id, new.text, new.note);
+    END;
+    CREATE TRIGGER IF NOT EXISTS highlights_ad AFTER DELETE ON highlights BEGIN
+        INSERT INTO highlights_fts (highlights_fts, rowid, text, note)
+        VALUES ('delete', old.id, old.text, old.note);
+    END;
+    CREATE TRIGGER IF NOT EXISTS highlights_au AFTER UPDATE ON highlights BEGIN
+        INSERT INTO highlights_fts (highlights_fts, rowid, text, note)
+        VALUES ('delete', old.id, old.text, old.note);
+        INSERT INTO highlights_fts (rowid, text, note) VALUES (new.id, new.text, new.note);
+    END;";
+
+/// Migration 5: the full-text index of book contents
+fn create_content_index(conn: &Connection) -> Result<()> {
+    // Full-text index of book contents, one row per chapter
+    conn.execute(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS book_content USING fts5(
+            book_id UNINDEXED, chapter_href UNINDEXED, text
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Migration 6: lookup history and favorite words
+fn create_vocabulary(conn: &Connection) -> Result<()> {
+    // Words the user has successfully looked up
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS lookup_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            word TEXT NOT NULL,
+            looked_up_at INTEGER NOT NULL DEFAULT (unixepoch())
+        )",
+        [],
+    )?;
+
+    // The user's personal glossary of saved headwords
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS favorite_words (
+            word TEXT PRIMARY KEY,
+            added_at INTEGER NOT NULL DEFAULT (unixepoch())
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Migration 7: app-wide preferences
+fn create_preferences(conn: &Connection) -> Result<()> {
+    // App-wide user settings as strings; typed accessors parse them on read
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS preferences (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL,
+            updated_at INTEGER NOT NULL DEFAULT (unixepoch())
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Migration 8: tags for grouping books
+fn create_tags(conn: &Connection) -> Result<()> {
+    // User-defined shelves such as "to read"; tags are created on first use
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS tags (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE
+        );
+        CREATE TABLE IF NOT EXISTS book_tags (
+            book_id INTEGER NOT NULL REFERENCES books(id),
+            tag_id INTEGER NOT NULL REFERENCES tags(id),
+            PRIMARY KEY (book_id, tag_id)
+        );
+        CREATE INDEX IF NOT EXISTS idx_book_tags_tag ON book_tags(tag_id);",
+    )?;
+
+    Ok(())
+}
+
+/// Migration 9: delete a book's progress, sessions, settings, bookmarks,
+/// highlights and tag links along with it. SQLite can't add `ON DELETE CASCADE`
+/// to an existing table, so each one is rebuilt; rows already orphaned by
+/// earlier deletes are dropped on the way.
+fn cascade_book_deletes(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE reading_progress_new (
+            book_id INTEGER PRIMARY KEY REFERENCES books(id) ON DELETE CASCADE,
+            location TEXT NOT NULL,
+            percent REAL NOT NULL,
+            updated_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+        INSERT INTO reading_progress_new
+            SELECT book_id, location, percent, updated_at FROM reading_progress
+            WHERE book_id IN (SELECT id FROM books);
+        DROP TABLE reading_progress;
+        ALTER TABLE reading_progress_new RENAME TO reading_progress;
+
+        CREATE TABLE reading_sessions_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+            started_at INTEGER NOT NULL DEFAULT (unixepoch()),
+            ended_at INTEGER,
+            words_read INTEGER NOT NULL DEFAULT 0
+        );
+        INSERT INTO reading_sessions_new
+            SELECT id, book_id, started_at, ended_at, words_read FROM reading_sessions
+            WHERE book_id IN (SELECT id FROM books);
+        DROP TABLE reading_sessions;
+        ALTER TABLE reading_sessions_new RENAME TO reading_sessions;
+        CREATE INDEX idx_reading_sessions_book ON reading_sessions(book_id);
+
+        CREATE TABLE book_settings_new (
+            book_id INTEGER PRIMARY KEY REFERENCES books(id) ON DELETE CASCADE,
+            font_family TEXT,
+            font_size REAL,
+            theme TEXT,
+            line_height REAL,
+            margin REAL,
+            updated_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+        INSERT INTO book_settings_new
+            SELECT book_id, font_family, font_size, theme, line_height, margin, updated_at
+            FROM book_settings WHERE book_id IN (SELECT id FROM books);
+        DROP TABLE book_settings;
+        ALTER TABLE book_settings_new RENAME TO book_settings;
+
+        CREATE TABLE bookmarks_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+            location TEXT NOT NULL,
+            label TEXT,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+        INSERT INTO bookmarks_new
+            SELECT id, book_id, location, label, created_at FROM bookmarks
+            WHERE book_id IN (SELECT id FROM books);
+        DROP TABLE bookmarks;
+        ALTER TABLE bookmarks_new RENAME TO bookmarks;
+        CREATE INDEX idx_bookmarks_book ON bookmarks(book_id);
+
+        CREATE TABLE highlights_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+            location TEXT NOT NULL,
+            text TEXT NOT NULL,
+            color TEXT NOT NULL,
+            note TEXT,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+        INSERT INTO highlights_new
+            SELECT id, book_id, location, text, color, note, created_at FROM highlights
+            WHERE book_id IN (SELECT id FROM books);
+        DROP TABLE highlights;
+        ALTER TABLE highlights_new RENAME TO highlights;
+        CREATE INDEX idx_highlights_book ON highlights(book_id);
+
+        CREATE TABLE book_tags_new (
+            book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+            tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+            PRIMARY KEY (book_id, tag_id)
+        );
+        INSERT INTO book_tags_new
+            SELECT book_id, tag_id FROM book_tags
+            WHERE book_id IN (SELECT id FROM books) AND tag_id IN (SELECT id FROM tags);
+        DROP TABLE book_tags;
+        ALTER TABLE book_tags_new RENAME TO book_tags;
+        CREATE INDEX idx_book_tags_tag ON book_tags(tag_id);",
+    )?;
+
+    // The rebuilt highlights table lost its triggers, and the index may still
+    // hold orphaned highlights that weren't copied
+    conn.execute_batch(HIGHLIGHTS_FTS_TRIGGERS)?;
+    conn.execute(
+        "INSERT INTO highlights_fts (highlights_fts) VALUES ('rebuild')",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Migration 10: free-form notes on bookmarks
+fn add_bookmark_notes(conn: &Connection) -> Result<()> {
+    if !has_column(conn, "bookmarks", "note")? {
+        conn.execute("ALTER TABLE bookmarks ADD COLUMN note TEXT", [])?;
+    }
+
+    Ok(())
+}
+
+/// Migration 11: an explicit finished mark on books. Books whose progress
+/// already reached `FINISHED_PERCENT` start out finished as of that progress.
+fn add_finished_flag(conn: &Connection) -> Result<()> {
+    if !has_column(conn, "books", "finished_at")? {
+        conn.execute("ALTER TABLE books ADD COLUMN finished_at INTEGER", [])?;
+        conn.execute(
+            "UPDATE books SET finished_at = (
+                SELECT updated_at FROM reading_progress p WHERE p.book_id = books.id
+             )
+             WHERE id IN (SELECT book_id FROM reading_progress WHERE percent >= ?)",
+            params![FINISHED_PERCENT],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// FTS5 tables can't gain columns, so a dictionary created by an older build is
+/// copied into a table with the current columns. Rows from before sources existed
+/// are attributed to the bundled source.
+fn migrate_dictionary_columns(conn: &Connection) -> Result<()> {
+    let has_source = has_column(conn, "dictionary", "source")?;
+    let has_details = has_column(conn, "dictionary", "ipa")?;
+    if has_source && has_details {
+        return Ok(());
+    }
+
+    let source = if has_source {
+        "source".to_string()
+    } else {
+        format!("'{}'", BUNDLED_DICTIONARY_SOURCE)
+    };
+    let details = if has_details {
+        "part_of_speech, ipa"
+    } else {
+        "NULL, NULL"
+    };
+
+    conn.execute_batch(&format!(
+        "CREATE VIRTUAL TABLE dictionary_migrated USING fts5({});
+         INSERT INTO dictionary_migrated (word, definition, source, part_of_speech, ipa)
+            SELECT word, definition, {}, {} FROM dictionary;
+         DROP TABLE dictionary;
+         ALTER TABLE dictionary_migrated RENAME TO dictionary;",
+        DICTIONARY_FTS_COLUMNS, source, details
+    ))
+}
+
+/// Migration 12: rebuild the dictionary with a tokenizer that ignores diacritics.
+/// A tokenizer can't be changed in place, so the rows are copied over.
+fn fold_dictionary_diacritics(conn: &Connection) -> Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE VIRTUAL TABLE dictionary_folded USING fts5({}, {});
+         INSERT INTO dictionary_folded (word, definition, source, part_of_speech, ipa)
+            SELECT word, definition, source, part_of_speech, ipa FROM dictionary;
+         DROP TABLE dictionary;
+         ALTER TABLE dictionary_folded RENAME TO dictionary;",
+        DICTIONARY_FTS_COLUMNS, DICTIONARY_FTS_TOKENIZER
+    ))
+}
+
+/// Migration 13: collections, folders of books that can nest inside each other
+fn create_collections(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS collections (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            parent_id INTEGER REFERENCES collections(id) ON DELETE CASCADE,
+            created_at INTEGER NOT NULL DEFAULT (unixepoch())
+        );
+        CREATE INDEX IF NOT EXISTS idx_collections_parent ON collections(parent_id);
+        CREATE TABLE IF NOT EXISTS collection_books (
+            collection_id INTEGER NOT NULL REFERENCES collections(id) ON DELETE CASCADE,
+            book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+            PRIMARY KEY (collection_id, book_id)
+        );
+        CREATE INDEX IF NOT EXISTS idx_collection_books_book ON collection_books(book_id);",
+    )?;
+
+    Ok(())
+}
+
+/// Migration 14: digests of files already hashed, reused while a file's
+/// modification time and size are unchanged
+fn create_hash_cache(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS hash_cache (
+            path TEXT NOT NULL,
+            algorithm TEXT NOT NULL,
+            mtime_ns INTEGER NOT NULL,
+            size INTEGER NOT NULL,
+            digest TEXT NOT NULL,
+            PRIMARY KEY (path, algorithm)
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Migration 15: a trash for books, so deleting one can be undone
+fn add_book_trash(conn: &Connection) -> Result<()> {
+    if !has_column(conn, "books", "deleted_at")? {
+        conn.execute("ALTER TABLE books ADD COLUMN deleted_at INTEGER", [])?;
     }
 
-    Ok(conn)
+    Ok(())
+}
+
+/// Migration 16: the daily reading goal, a single row while one is set
+fn create_reading_goals(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS reading_goals (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            daily_minutes INTEGER NOT NULL CHECK (daily_minutes > 0),
+            updated_at INTEGER NOT NULL DEFAULT (unixepoch())
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Whether `table` has a column called `column`; works for virtual tables too
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info(?) WHERE name = ?)",
+        params![table, column],
+        |row| row.get(0),
+    )
 }
 
-/// Load dictionary data from JSON file or use embedded fallback
-fn load_dictionary_data(conn: &Connection, app_handle: Option<&tauri::AppHandle>) -> Result<()> {
-    let mut loaded = false;
+/// SQL functions the queries rely on; these have to be registered on every connection
+fn register_functions(conn: &Connection) -> Result<()> {
+    // fold(text): lowercase without diacritics, like the dictionary tokenizer
+    conn.create_scalar_function(
+        "fold",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let text: Option<String> = ctx.get(0)?;
+            Ok(text.map(|text| fold_diacritics(&text)))
+        },
+    )
+}
+
+/// Resolve the on-disk database path, creating the app data directory if needed
+fn db_path(app_handle: &tauri::AppHandle) -> Option<PathBuf> {
+    let app_dir = app_handle.path().app_data_dir().ok()?;
+    if let Err(e) = fs::create_dir_all(&app_dir) {
+        eprintln!(
+            "Failed to create app data directory, using in-memory database: {}",
+            e
+        );
+        return None;
+    }
+    Some(app_dir.join("dictionary.db"))
+}
+
+/// The app data directory holding the database and the cover cache, created if
+/// it doesn't exist yet, so the UI can point at it for backups or debugging
+#[tauri::command]
+pub fn get_data_dir(app_handle: tauri::AppHandle) -> Result<String, AppError> {
+    let app_dir = app_handle.path().app_data_dir()?;
+    fs::create_dir_all(&app_dir)?;
+    Ok(app_dir.to_string_lossy().into_owned())
+}
 
+/// Dictionary entries from the bundled JSON file, or the embedded fallback
+/// when it can't be read
+fn seed_entries(app_handle: Option<&tauri::AppHandle>) -> Vec<DictionaryEntry> {
     // Try to load from bundled resource
     if let Some(handle) = app_handle {
         if let Ok(resource_path) = handle.path().resolve(
@@ -71,161 +743,4959 @@ fn load_dictionary_data(conn: &Connection, app_handle: Option<&tauri::AppHandle>
         ) {
             if let Ok(json_content) = fs::read_to_string(&resource_path) {
                 if let Ok(data) = serde_json::from_str::<DictionaryData>(&json_content) {
-                    insert_entries(conn, &data.words)?;
-                    loaded = true;
-                    println!(
-                        "Loaded {} dictionary entries from bundled file",
-                        data.words.len()
-                    );
+                    return data.words;
                 }
             }
         }
     }
 
     // Fallback to embedded data if bundled file not found
-    if !loaded {
-        let fallback_entries = get_fallback_entries();
-        insert_entries(conn, &fallback_entries)?;
-        println!(
-            "Loaded {} fallback dictionary entries",
-            fallback_entries.len()
-        );
-    }
+    get_fallback_entries()
+}
 
-    Ok(())
+/// Insert seed entries in one transaction, since per-row autocommit would sync
+/// the database file once for every entry
+fn seed_dictionary(conn: &Connection, entries: &[DictionaryEntry]) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    insert_entries(&tx, entries)?;
+    tx.commit()
 }
 
+/// Insert seed entries under the bundled source with a reused statement
 fn insert_entries(conn: &Connection, entries: &[DictionaryEntry]) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "INSERT INTO dictionary (word, definition, source, part_of_speech, ipa)
+         VALUES (?, ?, ?, ?, ?)",
+    )?;
     for entry in entries {
-        conn.execute(
-            "INSERT INTO dictionary (word, definition) VALUES (?, ?)",
-            params![entry.word.to_lowercase(), entry.definition],
-        )?;
+        stmt.execute(params![
+            entry.word.to_lowercase(),
+            entry.definition,
+            BUNDLED_DICTIONARY_SOURCE,
+            entry.part_of_speech,
+            entry.ipa
+        ])?;
     }
     Ok(())
 }
 
-fn get_fallback_entries() -> Vec<DictionaryEntry> {
-    vec![
-        DictionaryEntry {
-            word: "algorithm".to_string(),
-            definition: "A step-by-step procedure for solving a problem.".to_string(),
-        },
-        DictionaryEntry {
-            word: "api".to_string(),
-            definition: "Application Programming Interface; protocols for building software."
-                .to_string(),
-        },
-        DictionaryEntry {
-            word: "array".to_string(),
-            definition: "A data structure containing a collection of elements.".to_string(),
-        },
-        DictionaryEntry {
-            word: "bank".to_string(),
-            definition: "An institution for handling money; also, the land beside water."
-                .to_string(),
-        },
-        DictionaryEntry {
-            word: "boolean".to_string(),
-            definition: "A data type with only two values: true or false.".to_string(),
-        },
-        DictionaryEntry {
-            word: "buffer".to_string(),
-            definition: "Temporary storage for data being transferred.".to_string(),
-        },
-        DictionaryEntry {
-            word: "cache".to_string(),
-            definition: "Storage for faster future data access.".to_string(),
-        },
-        DictionaryEntry {
-            word: "class".to_string(),
-            definition: "A blueprint for creating objects in OOP.".to_string(),
-        },
-        DictionaryEntry {
-            word: "compiler".to_string(),
-            definition: "A program that translates source code into machine code.".to_string(),
-        },
-        DictionaryEntry {
-            word: "database".to_string(),
-            definition: "An organized collection of structured data.".to_string(),
-        },
-        DictionaryEntry {
-            word: "debug".to_string(),
-            definition: "To find and fix errors in software.".to_string(),
-        },
-        DictionaryEntry {
-            word: "function".to_string(),
-            definition: "A reusable block of code that performs a task.".to_string(),
-        },
-        DictionaryEntry {
-            word: "interpreter".to_string(),
-            definition: "A program that executes instructions directly.".to_string(),
-        },
-        DictionaryEntry {
-            word: "loop".to_string(),
-            definition: "A construct that repeats a block of code.".to_string(),
-        },
-        DictionaryEntry {
-            word: "memory".to_string(),
-            definition: "Storage for data and instructions.".to_string(),
-        },
-        DictionaryEntry {
-            word: "object".to_string(),
-            definition: "An instance of a class with data and methods.".to_string(),
-        },
-        DictionaryEntry {
-            word: "pointer".to_string(),
-            definition: "A variable storing a memory address.".to_string(),
-        },
-        DictionaryEntry {
-            word: "recursion".to_string(),
-            definition: "A technique where a function calls itself.".to_string(),
-        },
-        DictionaryEntry {
-            word: "string".to_string(),
-            definition: "A sequence of characters representing text.".to_string(),
-        },
-        DictionaryEntry {
-            word: "variable".to_string(),
-            definition: "A named storage location for data.".to_string(),
-        },
-    ]
+/// An entry is only worth inserting if both fields have content
+fn is_valid_entry(entry: &DictionaryEntry) -> bool {
+    !entry.word.trim().is_empty() && !entry.definition.trim().is_empty()
 }
 
-#[tauri::command]
-pub fn search_dictionary(word: &str, state: tauri::State<DbState>) -> Result<Vec<String>, String> {
-    let conn = state.0.lock().unwrap();
-    let search_term = word.trim().to_lowercase();
+fn not_an_entry_array() -> AppError {
+    AppError::InvalidFormat("Expected a JSON array of {word, definition} objects".to_string())
+}
 
-    // First try exact match
-    let mut stmt = conn
-        .prepare("SELECT definition FROM dictionary WHERE word = ? COLLATE NOCASE")
-        .map_err(|e| e.to_string())?;
+/// Parse a JSON array of `{word, definition}` objects, counting malformed records
+fn parse_json_entries(content: &str) -> Result<(Vec<DictionaryEntry>, usize), AppError> {
+    let value: serde_json::Value = serde_json::from_str(content)
+        .map_err(|e| AppError::InvalidFormat(format!("Invalid dictionary JSON: {}", e)))?;
+
+    // Accept a bare array as well as the bundled `{"words": [...]}` layout
+    let records = match value {
+        serde_json::Value::Array(records) => records,
+        serde_json::Value::Object(mut object) => match object.remove("words") {
+            Some(serde_json::Value::Array(records)) => records,
+            _ => return Err(not_an_entry_array()),
+        },
+        _ => return Err(not_an_entry_array()),
+    };
+
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+    for record in records {
+        match serde_json::from_value::<DictionaryEntry>(record) {
+            Ok(entry) if is_valid_entry(&entry) => entries.push(entry),
+            _ => skipped += 1,
+        }
+    }
+
+    Ok((entries, skipped))
+}
+
+/// Parse a `word,definition` CSV with an optional header row. Optional third and
+/// fourth columns hold the part of speech and IPA pronunciation.
+fn parse_csv_entries(content: &str) -> (Vec<DictionaryEntry>, usize) {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+    for (index, record) in reader.records().enumerate() {
+        let Ok(record) = record else {
+            skipped += 1;
+            continue;
+        };
+
+        let is_header = index == 0
+            && record
+                .get(0)
+                .is_some_and(|field| field.trim().eq_ignore_ascii_case("word"));
+        if is_header {
+            continue;
+        }
+
+        match (record.len(), record.get(0), record.get(1)) {
+            (2..=4, Some(word), Some(definition)) => {
+                let entry = DictionaryEntry {
+                    word: word.to_string(),
+                    definition: definition.to_string(),
+                    part_of_speech: record.get(2).map(str::to_string),
+                    ipa: record.get(3).map(str::to_string),
+                };
+                if is_valid_entry(&entry) {
+                    entries.push(entry);
+                } else {
+                    skipped += 1;
+                }
+            }
+            _ => skipped += 1,
+        }
+    }
+
+    (entries, skipped)
+}
+
+/// Built-in entries used when the bundled dictionary file can't be read, as
+/// `(word, part of speech, IPA, definition)`
+const FALLBACK_ENTRIES: [(&str, &str, &str, &str); 20] = [
+    (
+        "algorithm",
+        "noun",
+        "/ˈælɡəˌrɪðəm/",
+        "A step-by-step procedure for solving a problem.",
+    ),
+    (
+        "api",
+        "noun",
+        "/ˌeɪ piː ˈaɪ/",
+        "Application Programming Interface; protocols for building software.",
+    ),
+    (
+        "array",
+        "noun",
+        "/əˈreɪ/",
+        "A data structure containing a collection of elements.",
+    ),
+    (
+        "bank",
+        "noun",
+        "/bæŋk/",
+        "An institution for handling money; also, the land beside water.",
+    ),
+    (
+        "boolean",
+        "noun",
+        "/ˈbuːliən/",
+        "A data type with only two values: true or false.",
+    ),
+    (
+        "buffer",
+        "noun",
+        "/ˈbʌfər/",
+        "Temporary storage for data being transferred.",
+    ),
+    (
+        "cache",
+        "noun",
+        "/kæʃ/",
+        "Storage for faster future data access.",
+    ),
+    (
+        "class",
+        "noun",
+        "/klæs/",
+        "A blueprint for creating objects in OOP.",
+    ),
+    (
+        "compiler",
+        "noun",
+        "/kəmˈpaɪlər/",
+        "A program that translates source code into machine code.",
+    ),
+    (
+        "database",
+        "noun",
+        "/ˈdeɪtəˌbeɪs/",
+        "An organized collection of structured data.",
+    ),
+    (
+        "debug",
+        "verb",
+        "/diːˈbʌɡ/",
+        "To find and fix errors in software.",
+    ),
+    (
+        "function",
+        "noun",
+        "/ˈfʌŋkʃən/",
+        "A reusable block of code that performs a task.",
+    ),
+    (
+        "interpreter",
+        "noun",
+        "/ɪnˈtɜːrprɪtər/",
+        "A program that executes instructions directly.",
+    ),
+    (
+        "loop",
+        "noun",
+        "/luːp/",
+        "A construct that repeats a block of code.",
+    ),
+    (
+        "memory",
+        "noun",
+        "/ˈmɛməri/",
+        "Storage for data and instructions.",
+    ),
+    (
+        "object",
+        "noun",
+        "/ˈɑːbdʒɛkt/",
+        "An instance of a class with data and methods.",
+    ),
+    (
+        "pointer",
+        "noun",
+        "/ˈpɔɪntər/",
+        "A variable storing a memory address.",
+    ),
+    (
+        "recursion",
+        "noun",
+        "/rɪˈkɜːrʒən/",
+        "A technique where a function calls itself.",
+    ),
+    (
+        "string",
+        "noun",
+        "/strɪŋ/",
+        "A sequence of characters representing text.",
+    ),
+    (
+        "variable",
+        "noun",
+        "/ˈvɛriəbəl/",
+        "A named storage location for data.",
+    ),
+];
+
+fn get_fallback_entries() -> Vec<DictionaryEntry> {
+    FALLBACK_ENTRIES
+        .iter()
+        .map(|&(word, part_of_speech, ipa, definition)| DictionaryEntry {
+            word: word.to_string(),
+            definition: definition.to_string(),
+            part_of_speech: Some(part_of_speech.to_string()),
+            ipa: Some(ipa.to_string()),
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+pub struct ImportResult {
+    pub imported: usize,
+    pub skipped: usize,
+}
+
+/// Rows inserted between `dictionary-import-progress` events
+const IMPORT_PROGRESS_INTERVAL: usize = 1000;
+
+/// Payload of the `dictionary-import-progress` event
+#[derive(Clone, Serialize)]
+pub struct ImportProgress {
+    pub processed: usize,
+    pub total: usize,
+}
+
+/// Import a user dictionary from a JSON array of `{word, definition}` objects or,
+/// for `.csv` files, a `word,definition[,part_of_speech[,ipa]]` CSV. Entries are filed under
+/// `source`, which defaults to the file name without its extension. A
+/// `dictionary-import-progress` event is emitted every thousand rows and once
+/// all rows are in.
+#[tauri::command]
+pub fn import_dictionary(
+    path: String,
+    source: Option<String>,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+) -> Result<ImportResult, AppError> {
+    let content = fs::read_to_string(&path)?;
+    let file = Path::new(&path);
+    let is_csv = file
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+
+    let source = source
+        .map(|source| source.trim().to_string())
+        .filter(|source| !source.is_empty())
+        .or_else(|| {
+            file.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        })
+        .ok_or_else(|| AppError::InvalidInput("Dictionary source name is missing".to_string()))?;
+
+    let (entries, skipped) = if is_csv {
+        parse_csv_entries(&content)
+    } else {
+        parse_json_entries(&content)?
+    };
+
+    let mut conn = state.0.get()?;
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO dictionary (word, definition, source, part_of_speech, ipa)
+             VALUES (?, ?, ?, ?, ?)",
+        )?;
+        let total = entries.len();
+        for (i, entry) in entries.iter().enumerate() {
+            stmt.execute(params![
+                entry.word.trim().to_lowercase(),
+                entry.definition.trim(),
+                &source,
+                non_empty(entry.part_of_speech.as_deref()),
+                non_empty(entry.ipa.as_deref())
+            ])?;
+
+            let processed = i + 1;
+            if processed % IMPORT_PROGRESS_INTERVAL == 0 || processed == total {
+                let _ = app_handle.emit(
+                    "dictionary-import-progress",
+                    ImportProgress { processed, total },
+                );
+            }
+        }
+    }
+    tx.commit()?;
+
+    Ok(ImportResult {
+        imported: entries.len(),
+        skipped,
+    })
+}
+
+/// A dictionary entry as written by `export_dictionary`
+#[derive(Serialize)]
+struct ExportedEntry {
+    word: String,
+    definition: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    part_of_speech: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ipa: Option<String>,
+}
+
+/// Write the entries of `source` (or of every dictionary) to `writer` as a JSON
+/// array, one row at a time
+fn write_dictionary(
+    conn: &Connection,
+    source: Option<&str>,
+    writer: impl std::io::Write,
+) -> Result<(), AppError> {
+    use serde::ser::{SerializeSeq, Serializer};
+
+    let mut stmt = conn.prepare(
+        "SELECT word, definition, source, part_of_speech, ipa FROM dictionary
+         WHERE ?1 IS NULL OR source = ?1
+         ORDER BY rowid",
+    )?;
+    let rows = stmt.query_map(params![source], |row| {
+        Ok(ExportedEntry {
+            word: row.get(0)?,
+            definition: row.get(1)?,
+            source: row.get(2)?,
+            part_of_speech: row.get(3)?,
+            ipa: row.get(4)?,
+        })
+    })?;
+
+    let serialize_error = |e: serde_json::Error| {
+        AppError::InvalidFormat(format!("Could not write dictionary: {}", e))
+    };
+    let mut serializer = serde_json::Serializer::new(writer);
+    let mut entries = serializer.serialize_seq(None).map_err(serialize_error)?;
+    for row in rows {
+        entries.serialize_element(&row?).map_err(serialize_error)?;
+    }
+    entries.end().map_err(serialize_error)?;
+
+    Ok(())
+}
+
+/// Export the dictionary as a JSON array of `{word, definition, source,
+/// part_of_speech, ipa}` objects that `import_dictionary` reads back, limited
+/// to the entries of `source` when given. With `path` the JSON is streamed to
+/// that file and the path is returned; otherwise the JSON itself is.
+#[tauri::command]
+pub fn export_dictionary(
+    source: Option<String>,
+    path: Option<String>,
+    state: tauri::State<DbState>,
+) -> Result<String, AppError> {
+    let conn = state.0.get()?;
+
+    match path {
+        Some(path) => {
+            let mut writer = std::io::BufWriter::new(fs::File::create(&path)?);
+            write_dictionary(&conn, source.as_deref(), &mut writer)?;
+            std::io::Write::flush(&mut writer)?;
+            Ok(path)
+        }
+        None => {
+            let mut json = Vec::new();
+            write_dictionary(&conn, source.as_deref(), &mut json)?;
+            // serde_json only writes UTF-8, so nothing is replaced
+            Ok(String::from_utf8_lossy(&json).into_owned())
+        }
+    }
+}
+
+/// Trim an optional field, treating blank values as missing
+fn non_empty(value: Option<&str>) -> Option<&str> {
+    value.map(str::trim).filter(|value| !value.is_empty())
+}
+
+/// Default number of definitions returned by `search_dictionary`
+const DEFAULT_SEARCH_LIMIT: usize = 50;
+/// The prefix fallback only offers a few close candidates
+const PREFIX_FALLBACK_LIMIT: usize = 3;
+
+/// The fuzzy fallback only suggests headwords this many edits away or closer
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+/// Number of "did you mean" suggestions offered for a misspelled word
+const MAX_SUGGESTIONS: usize = 5;
+
+/// A search result with its relevance, from 0 to 1. Scores are relative to the
+/// best result of the same search, so they only compare within one result set.
+#[derive(Serialize)]
+pub struct ScoredEntry {
+    #[serde(flatten)]
+    pub entry: DictionaryEntry,
+    pub score: f64,
+}
+
+#[derive(Serialize)]
+pub struct SearchResponse {
+    /// The word as searched for, lowercased and without surrounding punctuation
+    pub query: String,
+    /// The dictionary form the results were found under, when the query itself
+    /// had no entry but an uninflected form of it did
+    pub lemma: Option<String>,
+    pub results: Vec<ScoredEntry>,
+    /// Number of entries matching the query, which `results` may be limited to fewer of
+    pub total: usize,
+    /// Close spellings of the query, only filled in when `results` is empty
+    pub suggestions: Vec<String>,
+}
+
+/// How `search_dictionary` matches the query
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SearchMode {
+    /// Exact headword, falling back to a few prefix matches
+    Default,
+    /// Only the exact headword
+    Exact,
+    /// Headwords starting with the query
+    Prefix,
+    /// Definitions containing the query as a phrase
+    Phrase,
+}
+
+impl SearchMode {
+    /// Parse a mode name, treating a missing or empty name as the default
+    fn from_name(name: Option<&str>) -> Result<Self, AppError> {
+        match name.unwrap_or("").trim().to_lowercase().as_str() {
+            "" => Ok(Self::Default),
+            "exact" => Ok(Self::Exact),
+            "prefix" => Ok(Self::Prefix),
+            "phrase" => Ok(Self::Phrase),
+            other => Err(AppError::InvalidInput(format!(
+                "Unknown search mode '{}', expected exact, prefix or phrase",
+                other
+            ))),
+        }
+    }
+}
+
+/// Which part of an entry `search_dictionary` matches the query against
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SearchField {
+    /// The headword, with lemmas and suggestions
+    Word,
+    /// The definition text, for finding a term from its meaning
+    Definition,
+    /// The headword and the definition together
+    Both,
+}
+
+impl SearchField {
+    /// Parse a field name, treating a missing or empty name as `word`
+    fn from_name(name: Option<&str>) -> Result<Self, AppError> {
+        match name.unwrap_or("").trim().to_lowercase().as_str() {
+            "" | "word" => Ok(Self::Word),
+            "definition" => Ok(Self::Definition),
+            "both" => Ok(Self::Both),
+            other => Err(AppError::InvalidInput(format!(
+                "Unknown search field '{}', expected word, definition or both",
+                other
+            ))),
+        }
+    }
+}
+
+/// Look up a word, optionally only in the dictionary named `source`. By default
+/// an exact headword match is tried first, then its uninflected forms ("run" for
+/// "running"), and the closest prefix matches are offered without one; `mode`
+/// narrows this to `exact` headwords, `prefix` matches, or a `phrase` search
+/// through the definitions. When a headword search finds nothing, headwords
+/// within a couple of typos of the query are suggested.
+///
+/// `search_field` set to `definition` or `both` turns this into a reverse
+/// lookup: entries whose definition (or headword and definition) contain every
+/// word of the query are returned, so "memory management" finds "garbage
+/// collection". There `prefix` matches each word as a prefix and `phrase`
+/// requires the words in order. Whatever the mode, an entry whose headword is
+/// the query itself is listed first. Each result has a `score` from 0 to 1,
+/// which only compares with the other results of the same search. With
+/// `highlight` set, occurrences of the query inside each definition are wrapped
+/// in `<b>`/`</b>`.
+#[tauri::command]
+pub fn search_dictionary(
+    word: &str,
+    limit: Option<usize>,
+    source: Option<String>,
+    highlight: Option<bool>,
+    mode: Option<String>,
+    search_field: Option<String>,
+    state: tauri::State<DbState>,
+) -> Result<SearchResponse, AppError> {
+    let mode = SearchMode::from_name(mode.as_deref())?;
+    let field = SearchField::from_name(search_field.as_deref())?;
+    let conn = state.0.get()?;
+    lookup_word(
+        &conn,
+        word,
+        limit,
+        source.as_deref(),
+        highlight.unwrap_or(false),
+        mode,
+        field,
+    )
+}
+
+/// The lookup behind `search_dictionary`, recording the word in the history
+/// when something was found
+fn lookup_word(
+    conn: &Connection,
+    word: &str,
+    limit: Option<usize>,
+    source: Option<&str>,
+    highlight: bool,
+    mode: SearchMode,
+    field: SearchField,
+) -> Result<SearchResponse, AppError> {
+    let search_term = normalize_query(word);
+    let limit = limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+    let phrase = escape_fts_query(&search_term);
+    // highlight() rather than snippet() so long definitions come back whole
+    let definition = if highlight {
+        "highlight(dictionary, 1, '<b>', '</b>')"
+    } else {
+        "definition"
+    };
+
+    let mut results: Vec<ScoredEntry> = Vec::new();
+    let mut total = 0;
+    let mut lemma = None;
+
+    // Exact match, most relevant definition first, retried with the likely
+    // dictionary forms of an inflected word such as "running"
+    if field == SearchField::Word && matches!(mode, SearchMode::Default | SearchMode::Exact) {
+        let terms = std::iter::once(search_term.clone()).chain(lemmas(&search_term));
+        for term in terms {
+            (results, total) = fetch_entries(
+                conn,
+                definition,
+                "dictionary MATCH ?1 AND fold(word) = ?2 AND (?3 IS NULL OR source = ?3)",
+                params![escape_fts_query(&term), fold_diacritics(&term), &source],
+                &term,
+                limit,
+            )?;
+            if !results.is_empty() {
+                lemma = Some(term).filter(|term| *term != search_term);
+                break;
+            }
+        }
+    }
+
+    // Prefix match, either on request or when there was no exact match
+    if field == SearchField::Word
+        && (mode == SearchMode::Prefix || (mode == SearchMode::Default && results.is_empty()))
+    {
+        let prefix_limit = if mode == SearchMode::Prefix {
+            limit
+        } else {
+            limit.min(PREFIX_FALLBACK_LIMIT)
+        };
+        (results, total) = fetch_entries(
+            conn,
+            definition,
+            "word MATCH ?1 AND (?2 IS NULL OR source = ?2)",
+            params![format!("{}*", phrase), &source],
+            &search_term,
+            prefix_limit,
+        )?;
+    }
+
+    // The escaped query is already a quoted FTS phrase
+    if field == SearchField::Word && mode == SearchMode::Phrase {
+        (results, total) = fetch_entries(
+            conn,
+            definition,
+            "definition MATCH ?1 AND (?2 IS NULL OR source = ?2)",
+            params![&phrase, &source],
+            &search_term,
+            limit,
+        )?;
+    }
+
+    // Reverse lookup, where the query describes the entry rather than naming it
+    if field != SearchField::Word && !search_term.is_empty() {
+        let column = if field == SearchField::Definition {
+            "definition"
+        } else {
+            "dictionary"
+        };
+        let query = if mode == SearchMode::Phrase {
+            phrase.clone()
+        } else {
+            // Space-separated FTS strings must all match, in any order
+            search_term
+                .split_whitespace()
+                .map(|term| {
+                    let term = escape_fts_query(term);
+                    if mode == SearchMode::Prefix {
+                        format!("{}*", term)
+                    } else {
+                        term
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        (results, total) = fetch_entries(
+            conn,
+            definition,
+            &format!("{} MATCH ?1 AND (?2 IS NULL OR source = ?2)", column),
+            params![query, &source],
+            &search_term,
+            limit,
+        )?;
+    }
+
+    // History is a convenience, so a failed write must not lose the results.
+    // A description of a word isn't a word that was looked up.
+    if !results.is_empty() && field == SearchField::Word {
+        if let Err(e) = conn.execute(
+            "INSERT INTO lookup_history (word) VALUES (?)",
+            params![lemma.as_ref().unwrap_or(&search_term)],
+        ) {
+            eprintln!("Failed to record lookup history: {}", e);
+        }
+    }
+
+    // Spelling suggestions only make sense for a headword
+    let suggestions = if results.is_empty()
+        && !search_term.is_empty()
+        && field == SearchField::Word
+        && mode != SearchMode::Phrase
+    {
+        did_you_mean(conn, &search_term, source)?
+    } else {
+        Vec::new()
+    };
+
+    Ok(SearchResponse {
+        query: search_term,
+        lemma,
+        results,
+        total,
+        suggestions,
+    })
+}
+
+/// The best `limit` dictionary entries matching `condition`, selecting `definition`
+/// as the definition column, along with how many entries match in all. Entries
+/// whose headword is `exact` up to case and diacritics come first, ahead of
+/// better BM25 scores, so "type" isn't buried under "bytecode". They score 1;
+/// the rest score their BM25 rank as a fraction of the best rank returned.
+fn fetch_entries(
+    conn: &Connection,
+    definition: &str,
+    condition: &str,
+    args: &[&dyn rusqlite::ToSql],
+    exact: &str,
+    limit: usize,
+) -> Result<(Vec<ScoredEntry>, usize)> {
+    let total: i64 = conn.query_row(
+        &format!("SELECT count(*) FROM dictionary WHERE {}", condition),
+        args,
+        |row| row.get(0),
+    )?;
+
+    let exact = fold_diacritics(exact);
+    let limit = limit as i64;
+    let mut ranked_args = args.to_vec();
+    ranked_args.push(&exact);
+    ranked_args.push(&limit);
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT word, {}, part_of_speech, ipa, fold(word) = ?{} AS is_exact,
+            bm25(dictionary) AS rank
+         FROM dictionary
+         WHERE {}
+         ORDER BY is_exact DESC, rank LIMIT ?{}",
+        definition,
+        args.len() + 1,
+        condition,
+        args.len() + 2
+    ))?;
+    let rows = stmt.query_map(&*ranked_args, |row| {
+        Ok((
+            entry_from_row(row)?,
+            row.get::<_, bool>(4)?,
+            row.get::<_, f64>(5)?,
+        ))
+    })?;
+    let rows = rows.collect::<Result<Vec<_>>>()?;
+
+    // BM25 ranks are negative, better matches further below zero
+    let best_rank = rows.iter().map(|&(_, _, rank)| rank).fold(0.0, f64::min);
+    let entries = rows
+        .into_iter()
+        .map(|(entry, is_exact, rank)| {
+            let score = if is_exact || best_rank == 0.0 {
+                1.0
+            } else {
+                rank / best_rank
+            };
+            ScoredEntry { entry, score }
+        })
+        .collect();
+
+    Ok((entries, total as usize))
+}
+
+/// Headwords closest to a misspelled `term` by edit distance, nearest first
+fn did_you_mean(conn: &Connection, term: &str, source: Option<&str>) -> Result<Vec<String>> {
+    let mut stmt =
+        conn.prepare("SELECT DISTINCT word FROM dictionary WHERE ?1 IS NULL OR source = ?1")?;
+    let rows = stmt.query_map(params![source], |row| row.get::<_, String>(0))?;
+
+    let term_len = term.chars().count();
+    let mut candidates = Vec::new();
+    for row in rows {
+        let word = row?;
+        // Lengths further apart than the threshold can't be within it
+        if word.chars().count().abs_diff(term_len) > MAX_SUGGESTION_DISTANCE {
+            continue;
+        }
+        let distance = levenshtein(term, &word.to_lowercase());
+        if distance <= MAX_SUGGESTION_DISTANCE {
+            candidates.push((distance, word));
+        }
+    }
+
+    candidates.sort();
+    candidates.truncate(MAX_SUGGESTIONS);
+    Ok(candidates.into_iter().map(|(_, word)| word).collect())
+}
+
+/// Names of the installed dictionaries, for choosing which one to search
+#[tauri::command]
+pub fn list_dictionary_sources(state: tauri::State<DbState>) -> Result<Vec<String>, AppError> {
+    let conn = state.0.get()?;
+    let mut stmt = conn.prepare("SELECT DISTINCT source FROM dictionary ORDER BY source")?;
 
-    let rows = stmt
-        .query_map(params![&search_term], |row| row.get::<_, String>(0))
-        .map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
 
-    let mut results: Vec<String> = Vec::new();
+    let mut sources = Vec::new();
     for row in rows {
-        results.push(row.map_err(|e| e.to_string())?);
+        sources.push(row?);
     }
 
-    // If no exact match, try prefix match
-    if results.is_empty() {
-        let mut stmt = conn
-            .prepare("SELECT definition FROM dictionary WHERE word LIKE ? COLLATE NOCASE LIMIT 3")
-            .map_err(|e| e.to_string())?;
+    Ok(sources)
+}
+
+/// Look up several words at once, mapping each requested word to its exact-match
+/// definitions (empty when the word isn't in the dictionary)
+#[tauri::command]
+pub fn search_dictionary_batch(
+    words: Vec<String>,
+    state: tauri::State<DbState>,
+) -> Result<HashMap<String, Vec<String>>, AppError> {
+    let conn = state.0.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT definition FROM dictionary
+         WHERE dictionary MATCH ? AND fold(word) = ?
+         ORDER BY bm25(dictionary)",
+    )?;
 
-        let pattern = format!("{}%", search_term);
-        let rows = stmt
-            .query_map(params![&pattern], |row| row.get::<_, String>(0))
-            .map_err(|e| e.to_string())?;
+    let mut results = HashMap::with_capacity(words.len());
+    for word in words {
+        let search_term = normalize_query(&word);
+        let rows = stmt.query_map(
+            params![
+                escape_fts_query(&search_term),
+                fold_diacritics(&search_term)
+            ],
+            |row| row.get::<_, String>(0),
+        )?;
 
+        let mut definitions = Vec::new();
         for row in rows {
-            results.push(row.map_err(|e| e.to_string())?);
+            definitions.push(row?);
         }
+        results.insert(word, definitions);
     }
 
     Ok(results)
 }
+
+/// Whether the dictionary has an entry for exactly this headword, for marking
+/// which words in the text can be looked up without fetching definitions
+#[tauri::command]
+pub fn word_exists(word: &str, state: tauri::State<DbState>) -> Result<bool, AppError> {
+    let search_term = normalize_query(word);
+    if search_term.is_empty() {
+        return Ok(false);
+    }
+
+    let conn = state.0.get()?;
+    // The MATCH narrows to rows containing the word; fold() keeps only the headword itself
+    let exists = conn
+        .query_row(
+            "SELECT 1 FROM dictionary WHERE word MATCH ?1 AND fold(word) = ?2 LIMIT 1",
+            params![
+                escape_fts_query(&search_term),
+                fold_diacritics(&search_term)
+            ],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+
+    Ok(exists)
+}
+
+/// Lowercase a looked-up word and drop punctuation around it, so a word selected
+/// in the text as "Bank." is looked up as "bank". Accents are kept so they can
+/// be shown back, and only folded away when comparing headwords.
+fn normalize_query(word: &str) -> String {
+    let word = word.trim();
+    let stripped = word.trim_matches(|c: char| c.is_ascii_punctuation());
+    // Don't strip a query that is nothing but punctuation down to nothing
+    let word = if stripped.is_empty() { word } else { stripped };
+    word.to_lowercase()
+}
+
+/// Quote raw user input as an FTS5 string so operators like `"`, `*`, `-` or `:`
+/// are matched literally instead of being parsed as query syntax
+fn escape_fts_query(raw: &str) -> String {
+    format!("\"{}\"", raw.replace('"', "\"\""))
+}
+
+fn entry_from_row(row: &rusqlite::Row) -> Result<DictionaryEntry> {
+    Ok(DictionaryEntry {
+        word: row.get(0)?,
+        definition: row.get(1)?,
+        part_of_speech: row.get(2)?,
+        ipa: row.get(3)?,
+    })
+}
+
+/// Pick one headword per local calendar day. The date is hashed rather than used
+/// as a plain counter so consecutive days don't walk through the alphabet.
+#[tauri::command]
+pub fn word_of_the_day(state: tauri::State<DbState>) -> Result<DictionaryEntry, AppError> {
+    let conn = state.0.get()?;
+    let (today, headwords): (String, i64) = conn.query_row(
+        "SELECT date('now', 'localtime'), count(DISTINCT word) FROM dictionary",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    if headwords == 0 {
+        return Err(AppError::NotFound("The dictionary is empty".to_string()));
+    }
+
+    let digest = Sha256::digest(today.as_bytes());
+    let seed = u64::from_be_bytes(digest[..8].try_into().expect("SHA-256 digest is 32 bytes"));
+    let offset = (seed % headwords as u64) as i64;
+
+    let word: String = conn.query_row(
+        "SELECT DISTINCT word FROM dictionary ORDER BY word LIMIT 1 OFFSET ?",
+        params![offset],
+        |row| row.get(0),
+    )?;
+
+    conn.query_row(
+        "SELECT word, definition, part_of_speech, ipa FROM dictionary
+         WHERE dictionary MATCH ? AND word = ?
+         ORDER BY rowid LIMIT 1",
+        params![escape_fts_query(&word), &word],
+        entry_from_row,
+    )
+    .map_err(AppError::from)
+}
+
+/// Pick a random headword, different on every call, for vocabulary practice.
+/// Headwords are drawn evenly however many definitions each one has.
+#[tauri::command]
+pub fn random_word(state: tauri::State<DbState>) -> Result<DictionaryEntry, AppError> {
+    let conn = state.0.get()?;
+    let word: String = conn
+        .query_row(
+            "SELECT word FROM (SELECT DISTINCT word FROM dictionary) ORDER BY random() LIMIT 1",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("The dictionary is empty".to_string()))?;
+
+    conn.query_row(
+        "SELECT word, definition, part_of_speech, ipa FROM dictionary
+         WHERE dictionary MATCH ? AND word = ?
+         ORDER BY rowid LIMIT 1",
+        params![escape_fts_query(&word), &word],
+        entry_from_row,
+    )
+    .map_err(AppError::from)
+}
+
+#[derive(Serialize)]
+pub struct EntryCount {
+    pub word: String,
+    pub count: usize,
+}
+
+/// How many definitions each headword has, most first, to find words with
+/// several senses or entries duplicated by an import. With `min_count` only
+/// headwords with at least that many definitions are listed.
+#[tauri::command]
+pub fn entry_counts(
+    min_count: Option<usize>,
+    state: tauri::State<DbState>,
+) -> Result<Vec<EntryCount>, AppError> {
+    let conn = state.0.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT word, count(*) AS definitions FROM dictionary
+         GROUP BY word HAVING definitions >= ?
+         ORDER BY definitions DESC, word",
+    )?;
+
+    let rows = stmt.query_map(params![min_count.unwrap_or(1) as i64], |row| {
+        Ok(EntryCount {
+            word: row.get(0)?,
+            count: row.get::<_, i64>(1)? as usize,
+        })
+    })?;
+
+    let mut counts = Vec::new();
+    for row in rows {
+        counts.push(row?);
+    }
+
+    Ok(counts)
+}
+
+/// Throw away every dictionary entry, imported and user-written ones included,
+/// and start over with the bundled dictionary in one transaction. Books,
+/// highlights and other personal data are left alone. Returns how many entries
+/// the dictionary holds afterwards.
+#[tauri::command]
+pub fn reset_dictionary(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+) -> Result<usize, AppError> {
+    // Read the seed file before checking out a connection
+    let entries = seed_entries(Some(&app_handle));
+
+    let mut conn = state.0.get()?;
+    let tx = conn.transaction()?;
+    // Recreated rather than emptied so the FTS index starts out compact
+    tx.execute_batch(&format!(
+        "DROP TABLE dictionary;
+         CREATE VIRTUAL TABLE dictionary USING fts5({}, {});",
+        DICTIONARY_FTS_COLUMNS, DICTIONARY_FTS_TOKENIZER
+    ))?;
+    insert_entries(&tx, &entries)?;
+    tx.commit()?;
+
+    Ok(entries.len())
+}
+
+/// Put between the definitions of headwords folded together by `merge_duplicate_words`
+const MERGED_DEFINITION_SEPARATOR: &str = "\n\n";
+
+/// Fold each headword that appears more than once in the same dictionary into
+/// a single entry, keeping the first row's part of speech and pronunciation
+/// (or the first ones given) and joining the distinct definitions in import
+/// order. Returns how many extra rows were merged away.
+#[tauri::command]
+pub fn merge_duplicate_words(state: tauri::State<DbState>) -> Result<usize, AppError> {
+    let mut conn = state.0.get()?;
+    let tx = conn.transaction()?;
+    let merged_rows = merge_duplicates(&tx)?;
+    tx.commit()?;
+
+    Ok(merged_rows)
+}
+
+/// The merge behind `merge_duplicate_words`, run inside the caller's transaction
+fn merge_duplicates(tx: &Connection) -> Result<usize> {
+    let mut groups: Vec<(Vec<i64>, DictionaryEntry, Option<String>)> = Vec::new();
+    {
+        let mut stmt = tx.prepare(
+            "SELECT rowid, word, definition, part_of_speech, ipa, source FROM (
+                SELECT rowid, *, count(*) OVER (PARTITION BY word, source) AS copies
+                FROM dictionary
+             )
+             WHERE copies > 1
+             ORDER BY word, source, rowid",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let rowid: i64 = row.get(0)?;
+            let source: Option<String> = row.get(5)?;
+            let entry = DictionaryEntry {
+                word: row.get(1)?,
+                definition: row.get(2)?,
+                part_of_speech: row.get(3)?,
+                ipa: row.get(4)?,
+            };
+
+            match groups.last_mut() {
+                Some((rowids, merged, merged_source))
+                    if merged.word == entry.word && *merged_source == source =>
+                {
+                    rowids.push(rowid);
+                    let seen = merged
+                        .definition
+                        .split(MERGED_DEFINITION_SEPARATOR)
+                        .any(|definition| definition == entry.definition);
+                    if !seen {
+                        merged.definition.push_str(MERGED_DEFINITION_SEPARATOR);
+                        merged.definition.push_str(&entry.definition);
+                    }
+                    merged.part_of_speech = merged.part_of_speech.take().or(entry.part_of_speech);
+                    merged.ipa = merged.ipa.take().or(entry.ipa);
+                }
+                _ => groups.push((vec![rowid], entry, source)),
+            }
+        }
+    }
+
+    // FTS5 rows are replaced outright rather than updated in place. The merged
+    // entry is re-inserted under the first row's rowid so it survives.
+    let mut merged_rows = 0;
+    for (rowids, entry, source) in &groups {
+        for rowid in rowids {
+            tx.execute("DELETE FROM dictionary WHERE rowid = ?", params![rowid])?;
+        }
+        tx.execute(
+            "INSERT INTO dictionary (rowid, word, definition, source, part_of_speech, ipa)
+             VALUES (?, ?, ?, ?, ?, ?)",
+            params![
+                rowids[0],
+                entry.word,
+                entry.definition,
+                source,
+                entry.part_of_speech,
+                entry.ipa
+            ],
+        )?;
+        merged_rows += rowids.len() - 1;
+    }
+
+    Ok(merged_rows)
+}
+
+/// Trim and lowercase a headword and trim its definition the way imports do,
+/// rejecting either when it's blank
+fn clean_entry(word: &str, definition: &str) -> Result<(String, String), AppError> {
+    let word = word.trim().to_lowercase();
+    let definition = definition.trim().to_string();
+    if word.is_empty() || definition.is_empty() {
+        return Err(AppError::InvalidInput(
+            "A dictionary entry needs a word and a definition".to_string(),
+        ));
+    }
+    Ok((word, definition))
+}
+
+/// Add a definition of the user's own, filed under the `user` source. Returns
+/// the new entry's rowid.
+#[tauri::command]
+pub fn add_entry(
+    word: &str,
+    definition: &str,
+    state: tauri::State<DbState>,
+) -> Result<i64, AppError> {
+    let (word, definition) = clean_entry(word, definition)?;
+    let conn = state.0.get()?;
+    conn.execute(
+        "INSERT INTO dictionary (word, definition, source) VALUES (?, ?, ?)",
+        params![word, definition, USER_DICTIONARY_SOURCE],
+    )?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+/// Change the headword and definition of the entry with `rowid`, keeping its
+/// source, part of speech and pronunciation. Returns the entry's rowid, which
+/// stays the same.
+#[tauri::command]
+pub fn update_entry(
+    rowid: i64,
+    word: &str,
+    definition: &str,
+    state: tauri::State<DbState>,
+) -> Result<i64, AppError> {
+    let (word, definition) = clean_entry(word, definition)?;
+    let mut conn = state.0.get()?;
+    let tx = conn.transaction()?;
+
+    let (source, part_of_speech, ipa): (Option<String>, Option<String>, Option<String>) = tx
+        .query_row(
+            "SELECT source, part_of_speech, ipa FROM dictionary WHERE rowid = ?",
+            params![rowid],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("Dictionary entry {} not found", rowid)))?;
+
+    // FTS5 rows are replaced rather than updated in place, as merge_duplicate_words does
+    tx.execute("DELETE FROM dictionary WHERE rowid = ?", params![rowid])?;
+    tx.execute(
+        "INSERT INTO dictionary (rowid, word, definition, source, part_of_speech, ipa)
+         VALUES (?, ?, ?, ?, ?, ?)",
+        params![rowid, word, definition, source, part_of_speech, ipa],
+    )?;
+    tx.commit()?;
+
+    Ok(rowid)
+}
+
+/// Remove the dictionary entry with `rowid`, returning it
+#[tauri::command]
+pub fn delete_entry(rowid: i64, state: tauri::State<DbState>) -> Result<i64, AppError> {
+    let conn = state.0.get()?;
+    let deleted = conn.execute("DELETE FROM dictionary WHERE rowid = ?", params![rowid])?;
+
+    if deleted == 0 {
+        return Err(AppError::NotFound(format!(
+            "Dictionary entry {} not found",
+            rowid
+        )));
+    }
+
+    Ok(rowid)
+}
+
+/// Entries around `around_word` in alphabetical order, for leafing through the
+/// dictionary like a printed one: up to `before` entries sorting before it,
+/// followed by up to `after` entries from it onwards. An empty word starts at
+/// the beginning of the alphabet.
+#[tauri::command]
+pub fn browse_entries(
+    around_word: String,
+    before: usize,
+    after: usize,
+    state: tauri::State<DbState>,
+) -> Result<Vec<DictionaryEntry>, AppError> {
+    let word = normalize_query(&around_word);
+    let conn = state.0.get()?;
+
+    let mut entries: Vec<DictionaryEntry> = conn
+        .prepare(
+            "SELECT word, definition, part_of_speech, ipa FROM dictionary
+             WHERE word < ?
+             ORDER BY word DESC, rowid DESC LIMIT ?",
+        )?
+        .query_map(params![word, before as i64], entry_from_row)?
+        .collect::<Result<_>>()?;
+    // Fetched nearest first, so flip them back into alphabetical order
+    entries.reverse();
+
+    let mut stmt = conn.prepare(
+        "SELECT word, definition, part_of_speech, ipa FROM dictionary
+         WHERE word >= ?
+         ORDER BY word, rowid LIMIT ?",
+    )?;
+    let rows = stmt.query_map(params![word, after as i64], entry_from_row)?;
+    for row in rows {
+        entries.push(row?);
+    }
+
+    Ok(entries)
+}
+
+/// Autocomplete headwords starting with `prefix`, shortest first
+#[tauri::command]
+pub fn suggest_words(
+    prefix: &str,
+    limit: usize,
+    state: tauri::State<DbState>,
+) -> Result<Vec<String>, AppError> {
+    let conn = state.0.get()?;
+    complete_word(&conn, prefix, limit).map_err(AppError::from)
+}
+
+/// The query behind `suggest_words`
+fn complete_word(conn: &Connection, prefix: &str, limit: usize) -> Result<Vec<String>> {
+    let prefix = prefix.trim().to_lowercase();
+    if prefix.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT word FROM dictionary WHERE word MATCH ?
+         ORDER BY length(word), word LIMIT ?",
+    )?;
+
+    // A quoted FTS string followed by `*` matches it as a prefix
+    let rows = stmt.query_map(
+        params![format!("{}*", escape_fts_query(&prefix)), limit as i64],
+        |row| row.get::<_, String>(0),
+    )?;
+
+    let mut words = Vec::new();
+    for row in rows {
+        words.push(row?);
+    }
+
+    Ok(words)
+}
+
+const BOOK_COLUMNS: &str =
+    "id, title, author, file_path, file_hash, added_at, last_opened, finished_at, deleted_at";
+
+fn book_from_row(row: &rusqlite::Row) -> Result<Book> {
+    Ok(Book {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        author: row.get(2)?,
+        file_path: row.get(3)?,
+        file_hash: row.get(4)?,
+        added_at: row.get(5)?,
+        last_opened: row.get(6)?,
+        finished_at: row.get(7)?,
+        deleted_at: row.get(8)?,
+    })
+}
+
+#[derive(Serialize)]
+pub struct AddBookResult {
+    pub id: i64,
+    pub was_duplicate: bool,
+    /// Other books whose titles nearly match, when `add_book` was asked to look
+    pub similar_books: Vec<Book>,
+}
+
+/// How alike two normalized titles must be, as `1 - distance / longer length`,
+/// for `add_book` to offer one as a possible duplicate of the other
+const SIMILAR_TITLE_RATIO: f64 = 0.85;
+
+/// A title with case, diacritics, punctuation and extra spaces stripped, so
+/// "The Time Machine" and "the time-machine" compare as the same words
+fn normalize_title(title: &str) -> String {
+    fold_diacritics(title)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Books other than `book_id` whose titles are within `SIMILAR_TITLE_RATIO` of
+/// `title`, most alike first
+fn similar_books(conn: &Connection, book_id: i64, title: &str) -> Result<Vec<Book>> {
+    let title = normalize_title(title);
+    if title.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM books WHERE id != ? AND deleted_at IS NULL",
+        BOOK_COLUMNS
+    ))?;
+    let rows = stmt.query_map(params![book_id], book_from_row)?;
+
+    let mut similar = Vec::new();
+    for row in rows {
+        let book = row?;
+        let other = normalize_title(&book.title);
+        let longest = title.chars().count().max(other.chars().count());
+        let ratio = 1.0 - levenshtein(&title, &other) as f64 / longest as f64;
+        if ratio >= SIMILAR_TITLE_RATIO {
+            similar.push((ratio, book));
+        }
+    }
+    similar.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+    Ok(similar.into_iter().map(|(_, book)| book).collect())
+}
+
+/// Add a book to the library, storing its SHA-256 hash. If a file with the same
+/// hash is already in the library its id is returned instead of inserting a copy,
+/// and a copy sitting in the trash is restored. With `find_similar` set, a new
+/// book also comes back with the books whose titles nearly match its own, since
+/// another conversion of the same book hashes differently; the UI can then ask
+/// whether it's a duplicate.
+#[tauri::command]
+pub fn add_book(
+    path: String,
+    title: String,
+    author: Option<String>,
+    find_similar: Option<bool>,
+    state: tauri::State<DbState>,
+) -> Result<AddBookResult, AppError> {
+    // Hash before checking out a connection so one isn't held while reading the file
+    let file_hash = hash_file(Path::new(&path), HashAlgorithm::Sha256)?;
+
+    let conn = state.0.get()?;
+    let mut result = insert_book(&conn, &path, &title, author.as_deref(), &file_hash)?;
+    if !result.was_duplicate && find_similar.unwrap_or(false) {
+        result.similar_books = similar_books(&conn, result.id, &title)?;
+    }
+
+    Ok(result)
+}
+
+/// Insert a book that hashes to `file_hash`, or restore and return the one
+/// already in the library with that hash
+fn insert_book(
+    conn: &Connection,
+    path: &str,
+    title: &str,
+    author: Option<&str>,
+    file_hash: &str,
+) -> Result<AddBookResult> {
+    let inserted = conn.execute(
+        "INSERT INTO books (title, author, file_path, file_hash) VALUES (?, ?, ?, ?)
+         ON CONFLICT(file_hash) DO NOTHING",
+        params![title, author, path, file_hash],
+    )?;
+
+    if inserted > 0 {
+        return Ok(AddBookResult {
+            id: conn.last_insert_rowid(),
+            was_duplicate: false,
+            similar_books: Vec::new(),
+        });
+    }
+
+    let id = conn.query_row(
+        "UPDATE books SET deleted_at = NULL WHERE file_hash = ? RETURNING id",
+        params![file_hash],
+        |row| row.get(0),
+    )?;
+
+    Ok(AddBookResult {
+        id,
+        was_duplicate: true,
+        similar_books: Vec::new(),
+    })
+}
+
+/// Payload of the `bulk-add-progress` event, sent after each file
+/// `add_books_bulk` has read
+#[derive(Clone, Serialize)]
+pub struct BulkAddProgress {
+    pub path: String,
+    pub current: usize,
+    pub total: usize,
+}
+
+/// Result of `add_books_bulk`: how many files were new books or already in the
+/// library, and the error message for every file that couldn't be added, keyed
+/// by path
+#[derive(Serialize)]
+pub struct BulkAddResult {
+    pub added: usize,
+    pub duplicates: usize,
+    pub errors: HashMap<String, String>,
+}
+
+/// Title and author of a book file from its own metadata, falling back to the
+/// file name for formats without any or books that don't declare a title
+fn book_title_and_author(path: &str) -> Result<(String, Option<String>), AppError> {
+    let (title, author) = match sniff_format(Path::new(path))? {
+        FileFormat::Epub => {
+            let metadata = extract_epub_metadata(path.to_string())?;
+            (metadata.title, metadata.author)
+        }
+        FileFormat::Mobi => {
+            let metadata = extract_mobi_metadata(path.to_string())?;
+            (metadata.title, metadata.author)
+        }
+        FileFormat::Pdf | FileFormat::PlainText => (None, None),
+        FileFormat::Unknown => {
+            return Err(AppError::InvalidFormat(format!(
+                "{} is not an EPUB, PDF, MOBI or text file",
+                path
+            )))
+        }
+    };
+
+    let title = title.unwrap_or_else(|| {
+        Path::new(path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string())
+    });
+    Ok((title, author))
+}
+
+/// Add many book files at once, such as every file found in a folder, off the
+/// command thread. Each file is hashed and its title and author read from its
+/// metadata, emitting `bulk-add-progress` as it goes, and then all of them are
+/// inserted in one transaction. Files already in the library count as
+/// duplicates, and a file that can't be read is reported without stopping the
+/// rest.
+#[tauri::command]
+pub async fn add_books_bulk(
+    paths: Vec<String>,
+    state: tauri::State<'_, DbState>,
+    app_handle: tauri::AppHandle,
+) -> Result<BulkAddResult, AppError> {
+    let pool = state.0.clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let total = paths.len();
+        let mut result = BulkAddResult {
+            added: 0,
+            duplicates: 0,
+            errors: HashMap::new(),
+        };
+
+        // Read every file before checking out a connection so one isn't held meanwhile
+        let mut books = Vec::new();
+        for (i, path) in paths.into_iter().enumerate() {
+            let book = hash_file(Path::new(&path), HashAlgorithm::Sha256)
+                .and_then(|hash| Ok((hash, book_title_and_author(&path)?)));
+            let _ = app_handle.emit(
+                "bulk-add-progress",
+                BulkAddProgress {
+                    path: path.clone(),
+                    current: i + 1,
+                    total,
+                },
+            );
+            match book {
+                Ok((hash, (title, author))) => books.push((path, hash, title, author)),
+                Err(e) => {
+                    result.errors.insert(path, e.to_string());
+                }
+            }
+        }
+
+        let mut conn = pool.get()?;
+        let tx = conn.transaction()?;
+        for (path, hash, title, author) in books {
+            match insert_book(&tx, &path, &title, author.as_deref(), &hash) {
+                Ok(AddBookResult {
+                    was_duplicate: false,
+                    ..
+                }) => result.added += 1,
+                Ok(_) => result.duplicates += 1,
+                Err(e) => {
+                    result.errors.insert(path, AppError::from(e).to_string());
+                }
+            }
+        }
+        tx.commit()?;
+
+        Ok(result)
+    })
+    .await?
+}
+
+/// Calibre formats in the order `import_from_calibre` prefers them when a book
+/// has been stored in several
+const CALIBRE_FORMATS: [&str; 5] = ["EPUB", "AZW3", "MOBI", "PDF", "TXT"];
+
+/// Result of `import_from_calibre`: how many books were added or already in the
+/// library, the Calibre titles whose files weren't found, and the error message
+/// for every file that couldn't be added, keyed by path
+#[derive(Serialize)]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub duplicates: usize,
+    pub missing: Vec<String>,
+    pub errors: HashMap<String, String>,
+}
+
+/// A book as listed in a Calibre library
+struct CalibreBook {
+    title: String,
+    author: Option<String>,
+    /// Unix seconds
+    added_at: Option<i64>,
+    files: Vec<(String, PathBuf)>,
+}
+
+/// Read the books of the Calibre library whose `metadata.db` is at `db_path`,
+/// resolving each stored format to its file under the library folder
+fn calibre_books(db_path: &Path) -> Result<Vec<CalibreBook>, AppError> {
+    let library_dir = db_path.parent().unwrap_or(Path::new("."));
+    let calibre = Connection::open_with_flags(db_path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let invalid = |e: rusqlite::Error| {
+        AppError::InvalidFormat(format!("Not a Calibre library database: {}", e))
+    };
+
+    let mut stmt = calibre
+        .prepare(
+            "SELECT b.id, b.title, b.path, CAST(strftime('%s', b.timestamp) AS INTEGER),
+                (SELECT group_concat(name, ' & ') FROM (
+                    SELECT a.name FROM books_authors_link l JOIN authors a ON a.id = l.author
+                    WHERE l.book = b.id ORDER BY l.id
+                ))
+             FROM books b ORDER BY b.id",
+        )
+        .map_err(invalid)?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, Option<i64>>(3)?,
+            row.get::<_, Option<String>>(4)?,
+        ))
+    })?;
+
+    let mut formats = calibre
+        .prepare("SELECT upper(format), name FROM data WHERE book = ?")
+        .map_err(invalid)?;
+    let mut books = Vec::new();
+    for row in rows {
+        let (id, title, folder, added_at, author) = row?;
+        let mut files: Vec<(String, PathBuf)> = formats
+            .query_map(params![id], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .map(|row| {
+                row.map(|(format, name)| {
+                    let file_name = format!("{}.{}", name, format.to_lowercase());
+                    (format, library_dir.join(&folder).join(file_name))
+                })
+            })
+            .collect::<Result<_>>()?;
+        files.sort_by_key(|(format, _)| {
+            CALIBRE_FORMATS
+                .iter()
+                .position(|preferred| preferred == format)
+                .unwrap_or(CALIBRE_FORMATS.len())
+        });
+
+        books.push(CalibreBook {
+            title,
+            author,
+            added_at,
+            files,
+        });
+    }
+
+    Ok(books)
+}
+
+/// Add the books of a Calibre library from its `metadata.db`, off the command
+/// thread, keeping Calibre's titles, authors and dates added. Of the formats
+/// Calibre stored for a book the first found of EPUB, AZW3, MOBI, PDF and TXT
+/// is used, then any other. Books whose path or contents are already in the
+/// library count as duplicates, and books without any file on disk are listed
+/// as missing. `calibre-import-progress` is emitted after each book is read,
+/// and everything is inserted in one transaction.
+#[tauri::command]
+pub async fn import_from_calibre(
+    calibre_db_path: String,
+    state: tauri::State<'_, DbState>,
+    app_handle: tauri::AppHandle,
+) -> Result<ImportSummary, AppError> {
+    let pool = state.0.clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        import_calibre_library(&pool, Path::new(&calibre_db_path), |progress| {
+            let _ = app_handle.emit("calibre-import-progress", progress);
+        })
+    })
+    .await?
+}
+
+/// The import behind `import_from_calibre`, calling `on_progress` after each
+/// book, missing ones included
+fn import_calibre_library(
+    pool: &DbPool,
+    calibre_db_path: &Path,
+    mut on_progress: impl FnMut(BulkAddProgress),
+) -> Result<ImportSummary, AppError> {
+    let calibre = calibre_books(calibre_db_path)?;
+
+    let total = calibre.len();
+    let mut summary = ImportSummary {
+        imported: 0,
+        duplicates: 0,
+        missing: Vec::new(),
+        errors: HashMap::new(),
+    };
+
+    // Hash every file before checking out a connection so one isn't held meanwhile
+    let mut books = Vec::new();
+    for (i, book) in calibre.into_iter().enumerate() {
+        // A missing book is reported under the path of its preferred format
+        let path = match book.files.iter().find(|(_, file)| file.is_file()) {
+            Some((_, file)) => {
+                let path = file.to_string_lossy().into_owned();
+                match hash_file(file, HashAlgorithm::Sha256) {
+                    Ok(hash) => books.push((path.clone(), hash, book)),
+                    Err(e) => {
+                        summary.errors.insert(path.clone(), e.to_string());
+                    }
+                }
+                path
+            }
+            None => {
+                let path = book
+                    .files
+                    .first()
+                    .map(|(_, file)| file.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                summary.missing.push(book.title);
+                path
+            }
+        };
+        on_progress(BulkAddProgress {
+            path,
+            current: i + 1,
+            total,
+        });
+    }
+
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    for (path, hash, book) in books {
+        let known_path: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM books WHERE file_path = ?)",
+            params![path],
+            |row| row.get(0),
+        )?;
+        if known_path {
+            summary.duplicates += 1;
+            continue;
+        }
+
+        match insert_book(&tx, &path, &book.title, book.author.as_deref(), &hash) {
+            Ok(result) if result.was_duplicate => summary.duplicates += 1,
+            Ok(result) => {
+                if let Some(added_at) = book.added_at {
+                    tx.execute(
+                        "UPDATE books SET added_at = ? WHERE id = ?",
+                        params![added_at, result.id],
+                    )?;
+                }
+                summary.imported += 1;
+            }
+            Err(e) => {
+                summary.errors.insert(path, AppError::from(e).to_string());
+            }
+        }
+    }
+    tx.commit()?;
+
+    Ok(summary)
+}
+
+#[derive(Serialize)]
+pub struct BookPage {
+    pub items: Vec<Book>,
+    pub total: usize,
+}
+
+/// List a page of the library, most recently added first, leaving out books in
+/// the trash. `total` counts every such book so the UI can show how many pages
+/// there are. Without a `limit` the rest of the library from `offset` is
+/// returned.
+#[tauri::command]
+pub fn list_books(
+    limit: Option<usize>,
+    offset: Option<usize>,
+    state: tauri::State<DbState>,
+) -> Result<BookPage, AppError> {
+    let conn = state.0.get()?;
+    let total: i64 = conn.query_row(
+        "SELECT count(*) FROM books WHERE deleted_at IS NULL",
+        [],
+        |row| row.get(0),
+    )?;
+
+    // SQLite treats a negative LIMIT as no limit
+    let limit = limit.map_or(-1, |limit| limit as i64);
+    let offset = offset.unwrap_or(0) as i64;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM books WHERE deleted_at IS NULL
+         ORDER BY added_at DESC, id DESC LIMIT ? OFFSET ?",
+        BOOK_COLUMNS
+    ))?;
+
+    let rows = stmt.query_map(params![limit, offset], book_from_row)?;
+
+    let mut items = Vec::new();
+    for row in rows {
+        items.push(row?);
+    }
+
+    Ok(BookPage {
+        items,
+        total: total as usize,
+    })
+}
+
+/// One book by id, or `None` if there is no such book. Books in the trash are
+/// returned too, with `deleted_at` set.
+#[tauri::command]
+pub fn get_book(book_id: i64, state: tauri::State<DbState>) -> Result<Option<Book>, AppError> {
+    let conn = state.0.get()?;
+    conn.query_row(
+        &format!("SELECT {} FROM books WHERE id = ?", BOOK_COLUMNS),
+        params![book_id],
+        book_from_row,
+    )
+    .optional()
+    .map_err(AppError::from)
+}
+
+/// Correct a book's title and/or author, leaving fields that aren't given as
+/// they are. A blank author clears it; a blank title is rejected.
+#[tauri::command]
+pub fn update_book_metadata(
+    book_id: i64,
+    title: Option<String>,
+    author: Option<String>,
+    state: tauri::State<DbState>,
+) -> Result<Book, AppError> {
+    let title = title.map(|title| title.trim().to_string());
+    if title.as_deref() == Some("") {
+        return Err(AppError::InvalidInput(
+            "A book's title can't be empty".to_string(),
+        ));
+    }
+
+    let conn = state.0.get()?;
+    conn.query_row(
+        &format!(
+            "UPDATE books SET
+                title = COALESCE(?1, title),
+                author = CASE WHEN ?2 IS NULL THEN author ELSE NULLIF(trim(?2), '') END
+             WHERE id = ?3
+             RETURNING {}",
+            BOOK_COLUMNS
+        ),
+        params![title, author, book_id],
+        book_from_row,
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound(format!("Book {} not found", book_id)))
+}
+
+/// Escape `%`, `_` and `\` for a `LIKE` pattern used with `ESCAPE '\'`
+fn escape_like(raw: &str) -> String {
+    raw.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Find books whose title or author contains `query`, ignoring case and
+/// diacritics. Title matches rank above author matches, and matches at the start
+/// of the title above those further in. An empty query lists every book.
+#[tauri::command]
+pub fn search_books(query: &str, state: tauri::State<DbState>) -> Result<Vec<Book>, AppError> {
+    let folded = fold_diacritics(query.trim());
+    let conn = state.0.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM books
+         WHERE deleted_at IS NULL
+            AND (fold(title) LIKE ?1 ESCAPE '\\' OR fold(author) LIKE ?1 ESCAPE '\\')
+         ORDER BY
+            CASE
+                WHEN fold(title) = ?2 THEN 0
+                WHEN fold(title) LIKE ?3 ESCAPE '\\' THEN 1
+                WHEN fold(title) LIKE ?1 ESCAPE '\\' THEN 2
+                ELSE 3
+            END,
+            title COLLATE NOCASE, id",
+        BOOK_COLUMNS
+    ))?;
+
+    let escaped = escape_like(&folded);
+    let rows = stmt.query_map(
+        params![format!("%{}%", escaped), folded, format!("{}%", escaped)],
+        book_from_row,
+    )?;
+
+    let mut books = Vec::new();
+    for row in rows {
+        books.push(row?);
+    }
+
+    Ok(books)
+}
+
+/// Record that a book was just opened, for the "continue reading" shelf
+#[tauri::command]
+pub fn touch_book(book_id: i64, state: tauri::State<DbState>) -> Result<(), AppError> {
+    let conn = state.0.get()?;
+    let updated = conn.execute(
+        "UPDATE books SET last_opened = unixepoch() WHERE id = ?",
+        params![book_id],
+    )?;
+
+    if updated == 0 {
+        return Err(AppError::NotFound(format!("Book {} not found", book_id)));
+    }
+    Ok(())
+}
+
+/// Books that have been opened, most recently opened first
+#[tauri::command]
+pub fn recently_opened(limit: usize, state: tauri::State<DbState>) -> Result<Vec<Book>, AppError> {
+    let conn = state.0.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM books WHERE last_opened IS NOT NULL AND deleted_at IS NULL
+         ORDER BY last_opened DESC, id DESC LIMIT ?",
+        BOOK_COLUMNS
+    ))?;
+
+    let rows = stmt.query_map(params![limit as i64], book_from_row)?;
+
+    let mut books = Vec::new();
+    for row in rows {
+        books.push(row?);
+    }
+
+    Ok(books)
+}
+
+/// Mark a book as finished, keeping the original date if it already was, or as
+/// unfinished again
+#[tauri::command]
+pub fn set_finished(
+    book_id: i64,
+    finished: bool,
+    state: tauri::State<DbState>,
+) -> Result<(), AppError> {
+    let conn = state.0.get()?;
+    let updated = conn.execute(
+        "UPDATE books SET finished_at = CASE WHEN ? THEN COALESCE(finished_at, unixepoch()) END
+         WHERE id = ?",
+        params![finished, book_id],
+    )?;
+
+    if updated == 0 {
+        return Err(AppError::NotFound(format!("Book {} not found", book_id)));
+    }
+
+    Ok(())
+}
+
+/// Books marked as finished, most recently finished first
+#[tauri::command]
+pub fn list_finished(state: tauri::State<DbState>) -> Result<Vec<Book>, AppError> {
+    let conn = state.0.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM books WHERE finished_at IS NOT NULL AND deleted_at IS NULL
+         ORDER BY finished_at DESC, id DESC",
+        BOOK_COLUMNS
+    ))?;
+
+    let rows = stmt.query_map([], book_from_row)?;
+
+    let mut books = Vec::new();
+    for row in rows {
+        books.push(row?);
+    }
+
+    Ok(books)
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VerifyResult {
+    Ok,
+    Modified,
+    Missing,
+}
+
+/// Re-hash a book's file and compare it with the hash stored when it was added.
+/// Books added before hashes were recorded have nothing to compare against and
+/// are reported as `ok` as long as the file is still there.
+#[tauri::command]
+pub fn verify_book(book_id: i64, state: tauri::State<DbState>) -> Result<VerifyResult, AppError> {
+    let (file_path, stored_hash): (String, Option<String>) = {
+        let conn = state.0.get()?;
+        conn.query_row(
+            "SELECT file_path, file_hash FROM books WHERE id = ?",
+            params![book_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("Book {} not found", book_id)))?
+    };
+
+    let path = Path::new(&file_path);
+    if !path.is_file() {
+        return Ok(VerifyResult::Missing);
+    }
+
+    let Some(stored_hash) = stored_hash else {
+        return Ok(VerifyResult::Ok);
+    };
+
+    let current_hash = match hash_file(path, HashAlgorithm::Sha256) {
+        Ok(hash) => hash,
+        Err(AppError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(VerifyResult::Missing)
+        }
+        Err(e) => return Err(e),
+    };
+
+    if current_hash == stored_hash {
+        Ok(VerifyResult::Ok)
+    } else {
+        Ok(VerifyResult::Modified)
+    }
+}
+
+/// Books in the library whose files are no longer where they were added from,
+/// so the UI can offer to relink or delete them
+#[tauri::command]
+pub fn find_missing_books(state: tauri::State<DbState>) -> Result<Vec<Book>, AppError> {
+    let conn = state.0.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM books WHERE deleted_at IS NULL ORDER BY title COLLATE NOCASE, id",
+        BOOK_COLUMNS
+    ))?;
+
+    let rows = stmt.query_map([], book_from_row)?;
+
+    let mut missing = Vec::new();
+    for row in rows {
+        let book = row?;
+        if !Path::new(&book.file_path).is_file() {
+            missing.push(book);
+        }
+    }
+
+    Ok(missing)
+}
+
+/// Point a book at its file's new location. The file must hash to what was
+/// stored when the book was added; books from before hashes were recorded take
+/// on the new file's hash, unless another book already has it.
+#[tauri::command]
+pub fn relink_book(
+    book_id: i64,
+    new_path: String,
+    state: tauri::State<DbState>,
+) -> Result<Book, AppError> {
+    let stored_hash: Option<String> = {
+        let conn = state.0.get()?;
+        conn.query_row(
+            "SELECT file_hash FROM books WHERE id = ?",
+            params![book_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("Book {} not found", book_id)))?
+    };
+
+    // Hash before checking out a connection so one isn't held while reading
+    let hash = hash_file(Path::new(&new_path), HashAlgorithm::Sha256)?;
+
+    let conn = state.0.get()?;
+    match stored_hash {
+        Some(stored_hash) if stored_hash != hash => {
+            return Err(AppError::InvalidInput(format!(
+                "{} is not the file of book {}",
+                new_path, book_id
+            )));
+        }
+        Some(_) => {}
+        None => {
+            let other: Option<i64> = conn
+                .query_row(
+                    "SELECT id FROM books WHERE file_hash = ?",
+                    params![hash],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(other) = other {
+                return Err(AppError::InvalidInput(format!(
+                    "{} is already in the library as book {}",
+                    new_path, other
+                )));
+            }
+        }
+    }
+
+    conn.query_row(
+        &format!(
+            "UPDATE books SET file_path = ?, file_hash = ? WHERE id = ? RETURNING {}",
+            BOOK_COLUMNS
+        ),
+        params![new_path, hash, book_id],
+        book_from_row,
+    )
+    .map_err(AppError::from)
+}
+
+/// Hash a file like `get_file_hash`, but reuse the digest computed by an
+/// earlier call as long as the file's modification time and size haven't
+/// changed. A changed file is re-hashed and its cache entry replaced.
+#[tauri::command]
+pub fn cached_file_hash(
+    path: String,
+    algorithm: String,
+    state: tauri::State<DbState>,
+) -> Result<String, AppError> {
+    let algorithm = HashAlgorithm::from_name(&algorithm)?;
+    let metadata = fs::metadata(&path)?;
+    let size = metadata.len() as i64;
+    // Nanoseconds so that edits within the same second still bust the cache
+    let mtime_ns = metadata
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |mtime| mtime.as_nanos() as i64);
+
+    let cached: Option<String> = state
+        .0
+        .get()?
+        .query_row(
+            "SELECT digest FROM hash_cache
+             WHERE path = ? AND algorithm = ? AND mtime_ns = ? AND size = ?",
+            params![path, algorithm.name(), mtime_ns, size],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(digest) = cached {
+        return Ok(digest);
+    }
+
+    // Hash without holding a connection, as add_book does
+    let digest = hash_file(Path::new(&path), algorithm)?;
+    state.0.get()?.execute(
+        "INSERT INTO hash_cache (path, algorithm, mtime_ns, size, digest) VALUES (?, ?, ?, ?, ?)
+         ON CONFLICT(path, algorithm) DO UPDATE SET
+            mtime_ns = excluded.mtime_ns, size = excluded.size, digest = excluded.digest",
+        params![path, algorithm.name(), mtime_ns, size, digest],
+    )?;
+
+    Ok(digest)
+}
+
+/// Result of `backfill_hashes`: how many books were hashed, the books whose file
+/// no longer exists, and the error message for every other book that couldn't be
+#[derive(Serialize)]
+pub struct BackfillResult {
+    pub hashed: usize,
+    pub missing: Vec<i64>,
+    pub errors: HashMap<i64, String>,
+}
+
+/// Bytes used on disk by the library, by category
+#[derive(Serialize)]
+pub struct DiskUsage {
+    pub books_bytes: u64,
+    pub database_bytes: u64,
+    pub covers_bytes: u64,
+    pub total_bytes: u64,
+    /// Books whose files are gone and so count for nothing
+    pub missing_files: usize,
+}
+
+/// Add up the sizes of every book file, including those in the trash, of the
+/// database and of the cached covers. Only file metadata is read, so this stays
+/// quick for large libraries.
+#[tauri::command]
+pub fn library_disk_usage(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+) -> Result<DiskUsage, AppError> {
+    let conn = state.0.get()?;
+    let paths: Vec<String> = conn
+        .prepare("SELECT file_path FROM books")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+    let database_bytes = database_size(&conn)?;
+    drop(conn);
+
+    let mut books_bytes = 0;
+    let mut missing_files = 0;
+    for path in paths {
+        match fs::metadata(&path) {
+            Ok(metadata) if metadata.is_file() => books_bytes += metadata.len(),
+            _ => missing_files += 1,
+        }
+    }
+
+    // No covers have been cached until the directory exists
+    let covers_bytes = match fs::read_dir(covers_dir(&app_handle)?) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|entry| entry.metadata().ok())
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .sum(),
+        Err(_) => 0,
+    };
+
+    Ok(DiskUsage {
+        books_bytes,
+        database_bytes,
+        covers_bytes,
+        total_bytes: books_bytes + database_bytes + covers_bytes,
+        missing_files,
+    })
+}
+
+/// Full-text indexes merged into one b-tree by `optimize_database`
+const FTS_TABLES: [&str; 3] = ["dictionary", "highlights_fts", "book_content"];
+
+/// Database size in bytes before and after `optimize_database`
+#[derive(Serialize)]
+pub struct OptimizeResult {
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+/// Bytes the database takes on disk: its file plus the `-wal` file holding
+/// pages not yet checkpointed into it. Only file metadata is read, so this
+/// never waits on or writes to the database. An in-memory database is measured
+/// by its pages.
+fn database_size(conn: &Connection) -> Result<u64, AppError> {
+    let file: String = conn.query_row(
+        "SELECT file FROM pragma_database_list WHERE name = 'main'",
+        [],
+        |row| row.get(0),
+    )?;
+    if file.is_empty() {
+        let size = conn.query_row(
+            "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+            [],
+            |row| row.get::<_, i64>(0),
+        )?;
+        return Ok(size as u64);
+    }
+
+    // No -wal file exists until the first write, and none after a clean close
+    let wal = fs::metadata(format!("{}-wal", file)).map_or(0, |metadata| metadata.len());
+    Ok(fs::metadata(&file)?.len() + wal)
+}
+
+/// Merge the segments of every full-text index and `VACUUM` the database file
+/// to reclaim space left behind by deletes, off the command thread. Returns the
+/// size of the database before and after.
+#[tauri::command]
+pub async fn optimize_database(
+    state: tauri::State<'_, DbState>,
+) -> Result<OptimizeResult, AppError> {
+    let pool = state.0.clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = pool.get()?;
+        let size_before = database_size(&conn)?;
+
+        for table in FTS_TABLES {
+            conn.execute(
+                &format!("INSERT INTO {0} ({0}) VALUES ('optimize')", table),
+                [],
+            )?;
+        }
+        conn.execute("VACUUM", [])?;
+
+        Ok(OptimizeResult {
+            size_before,
+            size_after: database_size(&conn)?,
+        })
+    })
+    .await?
+}
+
+/// Hash the file of every book added without a hash, off the command thread,
+/// emitting `backfill-progress` after each book. A file with the same contents
+/// as a book already in the library is reported as an error and left unhashed.
+#[tauri::command]
+pub async fn backfill_hashes(
+    state: tauri::State<'_, DbState>,
+    app_handle: tauri::AppHandle,
+) -> Result<BackfillResult, AppError> {
+    let pool = state.0.clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let books: Vec<(i64, String)> = {
+            let conn = pool.get()?;
+            let mut stmt = conn.prepare(
+                "SELECT id, file_path FROM books
+                 WHERE file_hash IS NULL OR file_hash = '' ORDER BY id",
+            )?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<Result<_>>()?
+        };
+
+        let total = books.len();
+        let mut result = BackfillResult {
+            hashed: 0,
+            missing: Vec::new(),
+            errors: HashMap::new(),
+        };
+        for (i, (book_id, path)) in books.into_iter().enumerate() {
+            // Hash before checking out a connection so one isn't held while reading the file
+            let hashed = hash_file(Path::new(&path), HashAlgorithm::Sha256).and_then(|hash| {
+                pool.get()?.execute(
+                    "UPDATE books SET file_hash = ? WHERE id = ?",
+                    params![hash, book_id],
+                )?;
+                Ok(())
+            });
+            match hashed {
+                Ok(()) => result.hashed += 1,
+                Err(AppError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                    result.missing.push(book_id)
+                }
+                Err(AppError::Db(e))
+                    if e.sqlite_error_code() == Some(rusqlite::ErrorCode::ConstraintViolation) =>
+                {
+                    result.errors.insert(
+                        book_id,
+                        "Another book in the library has the same contents".to_string(),
+                    );
+                }
+                Err(e) => {
+                    result.errors.insert(book_id, e.to_string());
+                }
+            }
+            let _ = app_handle.emit(
+                "backfill-progress",
+                LibraryProgress {
+                    book_id,
+                    current: i + 1,
+                    total,
+                },
+            );
+        }
+
+        Ok(result)
+    })
+    .await?
+}
+
+/// Remember the reading position for a book, replacing any earlier one
+#[tauri::command]
+pub fn save_progress(
+    book_id: i64,
+    location: String,
+    percent: f64,
+    state: tauri::State<DbState>,
+) -> Result<(), AppError> {
+    let conn = state.0.get()?;
+    conn.execute(
+        "INSERT INTO reading_progress (book_id, location, percent) VALUES (?, ?, ?)
+         ON CONFLICT(book_id) DO UPDATE SET
+            location = excluded.location,
+            percent = excluded.percent,
+            updated_at = unixepoch()",
+        params![book_id, location, percent],
+    )?;
+
+    Ok(())
+}
+
+/// Load the saved reading position, or `None` if the book was never opened
+#[tauri::command]
+pub fn get_progress(
+    book_id: i64,
+    state: tauri::State<DbState>,
+) -> Result<Option<Progress>, AppError> {
+    let conn = state.0.get()?;
+    conn.query_row(
+        "SELECT book_id, location, percent, updated_at FROM reading_progress WHERE book_id = ?",
+        params![book_id],
+        progress_from_row,
+    )
+    .optional()
+    .map_err(AppError::from)
+}
+
+/// Where a chapter lies relative to the reading position
+#[derive(Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ChapterState {
+    Read,
+    Current,
+    Unread,
+}
+
+/// One spine document with its table-of-contents title, if it has one
+#[derive(Serialize)]
+pub struct ChapterStatus {
+    pub href: String,
+    pub title: Option<String>,
+    pub status: ChapterState,
+}
+
+/// Index of the spine document a saved `location` points into. An EPUB CFI
+/// names it by its step under the spine (`/6/4` is the second document);
+/// anything else is taken as a document href, with any fragment ignored.
+fn spine_position(location: &str, hrefs: &[String]) -> Option<usize> {
+    if let Some(steps) = location
+        .strip_prefix("epubcfi(/6/")
+        .or_else(|| location.strip_prefix("epubcfi(6/"))
+    {
+        let step: usize = steps
+            .split(|c: char| !c.is_ascii_digit())
+            .next()?
+            .parse()
+            .ok()?;
+        // Spine items sit at the even steps, starting from 2
+        return (step >= 2 && step.is_multiple_of(2) && step / 2 <= hrefs.len())
+            .then(|| step / 2 - 1);
+    }
+
+    let path = location.split('#').next().unwrap_or_default();
+    hrefs.iter().position(|href| {
+        href == path
+            || path.ends_with(&format!("/{}", href))
+            || href.ends_with(&format!("/{}", path))
+    })
+}
+
+/// Mark each chapter of an EPUB as read, current or unread from the book's saved
+/// reading position. A location that can't be matched to a chapter falls back
+/// to the saved percentage; a book that was never opened is entirely unread.
+#[tauri::command]
+pub fn chapter_progress(
+    book_id: i64,
+    path: String,
+    state: tauri::State<DbState>,
+) -> Result<Vec<ChapterStatus>, AppError> {
+    let spine = get_epub_spine(path.clone())?;
+    // The titles are only a nicety, so a broken navigation document just leaves them out
+    let toc = get_epub_toc(path).unwrap_or_default();
+
+    let progress: Option<(String, f64)> = {
+        let conn = state.0.get()?;
+        conn.query_row(
+            "SELECT location, percent FROM reading_progress WHERE book_id = ?",
+            params![book_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+    };
+
+    let hrefs: Vec<String> = spine.into_iter().map(|item| item.href).collect();
+    let current = progress.and_then(|(location, percent)| {
+        spine_position(&location, &hrefs).or_else(|| {
+            let last = hrefs.len().checked_sub(1)?;
+            Some(((percent.clamp(0.0, 1.0) * hrefs.len() as f64) as usize).min(last))
+        })
+    });
+
+    Ok(hrefs
+        .into_iter()
+        .enumerate()
+        .map(|(i, href)| {
+            let title = toc
+                .iter()
+                .find(|entry| entry.href.split('#').next() == Some(href.as_str()))
+                .map(|entry| entry.title.clone());
+            let status = match current {
+                Some(current) if i < current => ChapterState::Read,
+                Some(current) if i == current => ChapterState::Current,
+                _ => ChapterState::Unread,
+            };
+            ChapterStatus {
+                href,
+                title,
+                status,
+            }
+        })
+        .collect())
+}
+
+fn progress_from_row(row: &rusqlite::Row) -> Result<Progress> {
+    Ok(Progress {
+        book_id: row.get(0)?,
+        location: row.get(1)?,
+        percent: row.get(2)?,
+        updated_at: row.get(3)?,
+    })
+}
+
+/// Reading progress of every book that has any, keyed by book id, so the library
+/// can show it without a `get_progress` call per book
+#[tauri::command]
+pub fn all_progress(state: tauri::State<DbState>) -> Result<HashMap<i64, Progress>, AppError> {
+    let conn = state.0.get()?;
+    let mut stmt =
+        conn.prepare("SELECT book_id, location, percent, updated_at FROM reading_progress")?;
+
+    let rows = stmt.query_map([], progress_from_row)?;
+
+    let mut progress = HashMap::new();
+    for row in rows {
+        let row = row?;
+        progress.insert(row.book_id, row);
+    }
+
+    Ok(progress)
+}
+
+/// Remember a book's display settings, replacing any saved before
+#[tauri::command]
+pub fn save_book_settings(
+    book_id: i64,
+    settings: BookSettings,
+    state: tauri::State<DbState>,
+) -> Result<(), AppError> {
+    let conn = state.0.get()?;
+    conn.execute(
+        "INSERT INTO book_settings (book_id, font_family, font_size, theme, line_height, margin)
+         VALUES (?, ?, ?, ?, ?, ?)
+         ON CONFLICT(book_id) DO UPDATE SET
+            font_family = excluded.font_family,
+            font_size = excluded.font_size,
+            theme = excluded.theme,
+            line_height = excluded.line_height,
+            margin = excluded.margin,
+            updated_at = unixepoch()",
+        params![
+            book_id,
+            settings.font_family,
+            settings.font_size,
+            settings.theme,
+            settings.line_height,
+            settings.margin
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Load a book's display settings, or `None` if none were saved so the UI can
+/// use its global defaults
+#[tauri::command]
+pub fn get_book_settings(
+    book_id: i64,
+    state: tauri::State<DbState>,
+) -> Result<Option<BookSettings>, AppError> {
+    let conn = state.0.get()?;
+    conn.query_row(
+        "SELECT font_family, font_size, theme, line_height, margin FROM book_settings
+         WHERE book_id = ?",
+        params![book_id],
+        |row| {
+            Ok(BookSettings {
+                font_family: row.get(0)?,
+                font_size: row.get(1)?,
+                theme: row.get(2)?,
+                line_height: row.get(3)?,
+                margin: row.get(4)?,
+            })
+        },
+    )
+    .optional()
+    .map_err(AppError::from)
+}
+
+const BOOKMARK_COLUMNS: &str = "id, book_id, location, label, note, created_at";
+
+fn bookmark_from_row(row: &rusqlite::Row) -> Result<Bookmark> {
+    Ok(Bookmark {
+        id: row.get(0)?,
+        book_id: row.get(1)?,
+        location: row.get(2)?,
+        label: row.get(3)?,
+        note: row.get(4)?,
+        created_at: row.get(5)?,
+    })
+}
+
+/// Bookmark a location in a book and return the bookmark id
+#[tauri::command]
+pub fn add_bookmark(
+    book_id: i64,
+    location: String,
+    label: Option<String>,
+    note: Option<String>,
+    state: tauri::State<DbState>,
+) -> Result<i64, AppError> {
+    let conn = state.0.get()?;
+    conn.execute(
+        "INSERT INTO bookmarks (book_id, location, label, note) VALUES (?, ?, ?, ?)",
+        params![book_id, location, label, note],
+    )?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+/// List a book's bookmarks in reading order. Page numbers sort numerically,
+/// other locations such as CFIs fall back to text order.
+#[tauri::command]
+pub fn list_bookmarks(
+    book_id: i64,
+    state: tauri::State<DbState>,
+) -> Result<Vec<Bookmark>, AppError> {
+    let conn = state.0.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM bookmarks
+         WHERE book_id = ?
+         ORDER BY CAST(location AS REAL), location",
+        BOOKMARK_COLUMNS
+    ))?;
+
+    let rows = stmt.query_map(params![book_id], bookmark_from_row)?;
+
+    let mut bookmarks = Vec::new();
+    for row in rows {
+        bookmarks.push(row?);
+    }
+
+    Ok(bookmarks)
+}
+
+/// Change a bookmark's label and/or note, leaving fields that aren't given as
+/// they are. A blank value clears the field.
+#[tauri::command]
+pub fn update_bookmark(
+    id: i64,
+    label: Option<String>,
+    note: Option<String>,
+    state: tauri::State<DbState>,
+) -> Result<Bookmark, AppError> {
+    let conn = state.0.get()?;
+    conn.query_row(
+        &format!(
+            "UPDATE bookmarks SET
+                label = CASE WHEN ?1 IS NULL THEN label ELSE NULLIF(trim(?1), '') END,
+                note = CASE WHEN ?2 IS NULL THEN note ELSE NULLIF(trim(?2), '') END
+             WHERE id = ?3
+             RETURNING {}",
+            BOOKMARK_COLUMNS
+        ),
+        params![label, note, id],
+        bookmark_from_row,
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound(format!("Bookmark {} not found", id)))
+}
+
+#[tauri::command]
+pub fn delete_bookmark(id: i64, state: tauri::State<DbState>) -> Result<(), AppError> {
+    let conn = state.0.get()?;
+    let deleted = conn.execute("DELETE FROM bookmarks WHERE id = ?", params![id])?;
+    if deleted == 0 {
+        return Err(AppError::NotFound(format!("Bookmark {} not found", id)));
+    }
+
+    Ok(())
+}
+
+/// Highlight color used when the reader doesn't pick one
+const DEFAULT_HIGHLIGHT_COLOR: &str = "yellow";
+
+const HIGHLIGHT_COLUMNS: &str =
+    "h.id, h.book_id, h.location, h.text, h.color, h.note, h.created_at";
+
+fn highlight_from_row(row: &rusqlite::Row) -> Result<Highlight> {
+    Ok(Highlight {
+        id: row.get(0)?,
+        book_id: row.get(1)?,
+        location: row.get(2)?,
+        text: row.get(3)?,
+        color: row.get(4)?,
+        note: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+#[derive(Serialize)]
+pub struct AddHighlightResult {
+    pub id: i64,
+    pub was_duplicate: bool,
+}
+
+/// Save a highlighted passage and return its id. If the same passage at the
+/// same location is already highlighted, its id is returned instead of saving
+/// a copy, so a selection reported twice doesn't leave duplicates.
+#[tauri::command]
+pub fn add_highlight(
+    book_id: i64,
+    location: String,
+    text: String,
+    color: Option<String>,
+    note: Option<String>,
+    state: tauri::State<DbState>,
+) -> Result<AddHighlightResult, AppError> {
+    let color = color.unwrap_or_else(|| DEFAULT_HIGHLIGHT_COLOR.to_string());
+
+    let mut conn = state.0.get()?;
+    let tx = conn.transaction()?;
+    let existing: Option<i64> = tx
+        .query_row(
+            "SELECT id FROM highlights WHERE book_id = ? AND location = ? AND text = ?
+             ORDER BY id LIMIT 1",
+            params![book_id, location, text],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(id) = existing {
+        return Ok(AddHighlightResult {
+            id,
+            was_duplicate: true,
+        });
+    }
+
+    tx.execute(
+        "INSERT INTO highlights (book_id, location, text, color, note) VALUES (?, ?, ?, ?, ?)",
+        params![book_id, location, text, color, note],
+    )?;
+    let id = tx.last_insert_rowid();
+    tx.commit()?;
+
+    Ok(AddHighlightResult {
+        id,
+        was_duplicate: false,
+    })
+}
+
+/// A book's highlights in reading order
+fn book_highlights(conn: &Connection, book_id: i64) -> Result<Vec<Highlight>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM highlights h WHERE h.book_id = ?
+         ORDER BY CAST(h.location AS REAL), h.location",
+        HIGHLIGHT_COLUMNS
+    ))?;
+
+    let rows = stmt.query_map(params![book_id], highlight_from_row)?;
+
+    let mut highlights = Vec::new();
+    for row in rows {
+        highlights.push(row?);
+    }
+
+    Ok(highlights)
+}
+
+/// List a book's highlights in reading order
+#[tauri::command]
+pub fn list_highlights(
+    book_id: i64,
+    state: tauri::State<DbState>,
+) -> Result<Vec<Highlight>, AppError> {
+    let conn = state.0.get()?;
+    book_highlights(&conn, book_id).map_err(AppError::from)
+}
+
+/// Format a book's highlights as a Markdown notes file: the title as a heading,
+/// then each highlight as a blockquote with its note below it
+#[tauri::command]
+pub fn export_highlights_markdown(
+    book_id: i64,
+    state: tauri::State<DbState>,
+) -> Result<String, AppError> {
+    let conn = state.0.get()?;
+    let title: String = conn
+        .query_row(
+            "SELECT title FROM books WHERE id = ?",
+            params![book_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("Book {} not found", book_id)))?;
+
+    let mut markdown = format!("# {}\n", title.trim());
+    for highlight in book_highlights(&conn, book_id)? {
+        markdown.push('\n');
+        // Quote every line so multi-paragraph highlights stay inside the blockquote
+        for line in highlight.text.trim().lines() {
+            markdown.push_str(if line.trim().is_empty() { ">" } else { "> " });
+            markdown.push_str(line.trim_end());
+            markdown.push('\n');
+        }
+
+        if let Some(note) = highlight.note.as_deref().map(str::trim) {
+            if !note.is_empty() {
+                markdown.push('\n');
+                markdown.push_str(note);
+                markdown.push('\n');
+            }
+        }
+    }
+
+    Ok(markdown)
+}
+
+#[derive(Serialize)]
+pub struct HighlightPage {
+    pub items: Vec<Highlight>,
+    pub total: usize,
+}
+
+/// Full-text search over highlight text and notes across every book, or only
+/// `book_id`, optionally keeping just highlights of one `color`. An empty query
+/// lists the matching highlights newest first instead of by relevance. `total`
+/// counts every match so the UI can page through them with `limit` and `offset`.
+#[tauri::command]
+pub fn search_highlights(
+    query: &str,
+    book_id: Option<i64>,
+    color: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    state: tauri::State<DbState>,
+) -> Result<HighlightPage, AppError> {
+    let query = query.trim();
+    // Both forms take the query as ?1 so the filters number their parameters alike
+    let (from, order) = if query.is_empty() {
+        (
+            "highlights h WHERE ?1 IS NULL",
+            "h.created_at DESC, h.id DESC",
+        )
+    } else {
+        (
+            "highlights_fts JOIN highlights h ON h.id = highlights_fts.rowid
+             WHERE highlights_fts MATCH ?1",
+            "bm25(highlights_fts)",
+        )
+    };
+    let filters = "AND (?2 IS NULL OR h.book_id = ?2)
+         AND (?3 IS NULL OR h.color = ?3 COLLATE NOCASE)";
+    let query = Some(escape_fts_query(query)).filter(|_| !query.is_empty());
+    let color = color
+        .map(|color| color.trim().to_string())
+        .filter(|color| !color.is_empty());
+
+    let conn = state.0.get()?;
+    let total: i64 = conn.query_row(
+        &format!("SELECT count(*) FROM {} {}", from, filters),
+        params![query, book_id, color],
+        |row| row.get(0),
+    )?;
+
+    // SQLite treats a negative LIMIT as no limit
+    let limit = limit.map_or(-1, |limit| limit as i64);
+    let offset = offset.unwrap_or(0) as i64;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM {} {}
+         ORDER BY {} LIMIT ?4 OFFSET ?5",
+        HIGHLIGHT_COLUMNS, from, filters, order
+    ))?;
+
+    let rows = stmt.query_map(
+        params![query, book_id, color, limit, offset],
+        highlight_from_row,
+    )?;
+
+    let mut items = Vec::new();
+    for row in rows {
+        items.push(row?);
+    }
+
+    Ok(HighlightPage {
+        items,
+        total: total as usize,
+    })
+}
+
+/// Books with highlights or highlight notes matching `query`, those with the
+/// most matching highlights first. Books in the trash are left out.
+#[tauri::command]
+pub fn books_with_highlight_text(
+    query: &str,
+    state: tauri::State<DbState>,
+) -> Result<Vec<Book>, AppError> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let conn = state.0.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM books
+         JOIN (
+            SELECT h.book_id, count(*) AS matches FROM highlights_fts
+            JOIN highlights h ON h.id = highlights_fts.rowid
+            WHERE highlights_fts MATCH ?
+            GROUP BY h.book_id
+         ) m ON m.book_id = books.id
+         WHERE deleted_at IS NULL
+         ORDER BY m.matches DESC, title COLLATE NOCASE",
+        BOOK_COLUMNS
+    ))?;
+
+    let rows = stmt.query_map(params![escape_fts_query(query)], book_from_row)?;
+
+    let mut books = Vec::new();
+    for row in rows {
+        books.push(row?);
+    }
+
+    Ok(books)
+}
+
+#[derive(Serialize)]
+pub struct ColorCount {
+    pub color: String,
+    pub count: usize,
+}
+
+/// How many highlights there are of each color, most used first, across the
+/// library or in one book. Colors are compared ignoring case, as the color
+/// filter of `search_highlights` does, and reported in lowercase.
+#[tauri::command]
+pub fn highlight_color_stats(
+    book_id: Option<i64>,
+    state: tauri::State<DbState>,
+) -> Result<Vec<ColorCount>, AppError> {
+    let conn = state.0.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT lower(color) AS shade, count(*) AS highlights FROM highlights
+         WHERE ?1 IS NULL OR book_id = ?1
+         GROUP BY shade
+         ORDER BY highlights DESC, shade",
+    )?;
+
+    let rows = stmt.query_map(params![book_id], |row| {
+        Ok(ColorCount {
+            color: row.get(0)?,
+            count: row.get::<_, i64>(1)? as usize,
+        })
+    })?;
+
+    let mut counts = Vec::new();
+    for row in rows {
+        counts.push(row?);
+    }
+
+    Ok(counts)
+}
+
+/// Result of `index_book`: how many chapters or pages were indexed, and how
+/// many PDF pages were left out because their text couldn't be decoded
+#[derive(Serialize)]
+pub struct IndexResult {
+    pub indexed: usize,
+    pub skipped_pages: usize,
+}
+
+/// Index the text of every chapter of an EPUB, or every page of a PDF, for
+/// `search_library`, replacing anything indexed for the book before.
+#[tauri::command]
+pub fn index_book(
+    book_id: i64,
+    path: String,
+    state: tauri::State<DbState>,
+) -> Result<IndexResult, AppError> {
+    reindex_book(&state.0, book_id, &path)
+}
+
+/// Replace the indexed text of one book with freshly extracted text
+fn reindex_book(pool: &DbPool, book_id: i64, path: &str) -> Result<IndexResult, AppError> {
+    // Extract before checking out a connection so one isn't held while parsing
+    let (chapters, skipped_pages) = match sniff_format(Path::new(path))? {
+        FileFormat::Pdf => {
+            let pdf = extract_pages(path)?;
+            (pdf.pages, pdf.skipped)
+        }
+        _ => (extract_chapters(path)?, 0),
+    };
+
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    tx.execute(
+        "DELETE FROM book_content WHERE book_id = ?",
+        params![book_id],
+    )?;
+    {
+        let mut stmt =
+            tx.prepare("INSERT INTO book_content (book_id, chapter_href, text) VALUES (?, ?, ?)")?;
+        for chapter in &chapters {
+            stmt.execute(params![book_id, chapter.href, chapter.text])?;
+        }
+    }
+    tx.commit()?;
+
+    Ok(IndexResult {
+        indexed: chapters.len(),
+        skipped_pages,
+    })
+}
+
+/// Payload of the `indexing-progress` and `backfill-progress` events, sent after
+/// each book a library-wide job processes
+#[derive(Clone, Serialize)]
+pub struct LibraryProgress {
+    pub book_id: i64,
+    pub current: usize,
+    pub total: usize,
+}
+
+/// Result of `index_all_books`: how many books were indexed, how many PDF pages
+/// across them couldn't be decoded, and the error message for every book that
+/// couldn't be indexed, keyed by book id
+#[derive(Serialize)]
+pub struct LibraryIndexResult {
+    pub indexed: usize,
+    pub skipped_pages: usize,
+    pub errors: HashMap<i64, String>,
+}
+
+/// Rebuild the content index of every book in the library off the command
+/// thread, emitting `indexing-progress` after each book. A book that can't be
+/// read is reported and skipped rather than stopping the run.
+#[tauri::command]
+pub async fn index_all_books(
+    state: tauri::State<'_, DbState>,
+    app_handle: tauri::AppHandle,
+) -> Result<LibraryIndexResult, AppError> {
+    let pool = state.0.clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let books: Vec<(i64, String)> = {
+            let conn = pool.get()?;
+            let mut stmt = conn
+                .prepare("SELECT id, file_path FROM books WHERE deleted_at IS NULL ORDER BY id")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<Result<_>>()?
+        };
+
+        let total = books.len();
+        let mut result = LibraryIndexResult {
+            indexed: 0,
+            skipped_pages: 0,
+            errors: HashMap::new(),
+        };
+        for (i, (book_id, path)) in books.into_iter().enumerate() {
+            match reindex_book(&pool, book_id, &path) {
+                Ok(book) => {
+                    result.indexed += 1;
+                    result.skipped_pages += book.skipped_pages;
+                }
+                Err(e) => {
+                    result.errors.insert(book_id, e.to_string());
+                }
+            }
+            let _ = app_handle.emit(
+                "indexing-progress",
+                LibraryProgress {
+                    book_id,
+                    current: i + 1,
+                    total,
+                },
+            );
+        }
+
+        Ok(result)
+    })
+    .await?
+}
+
+/// Longest snippet `search_library` returns, in characters, before it is cut
+/// short around the match
+const SENTENCE_SNIPPET_MAX_CHARS: usize = 300;
+/// Tokens of chapter text `search_library` has FTS5 cut around each match, the
+/// most `snippet()` allows, leaving room to find the sentence inside them
+const SENTENCE_SNIPPET_TOKENS: i64 = 64;
+/// Stand-ins for `<b>` and `</b>` while a snippet is trimmed, one character
+/// each so a cut can never split a tag
+const MATCH_START: char = '\u{1}';
+const MATCH_END: char = '\u{2}';
+
+/// Cut the sentence holding the first match out of `marked` chapter text, whose
+/// matches are wrapped in `MATCH_START`/`MATCH_END`, and turn the markers into
+/// `<b>`/`</b>`. Sentences end at `.`, `!` or `?` followed by a space, or at a
+/// line break. A sentence longer than `max_chars` is trimmed evenly around the
+/// match, with `…` where text was left out.
+fn sentence_snippet(marked: &str, max_chars: usize) -> String {
+    let chars: Vec<char> = marked.chars().collect();
+    let Some(first) = chars.iter().position(|&c| c == MATCH_START) else {
+        return chars
+            .iter()
+            .take(max_chars)
+            .collect::<String>()
+            .trim()
+            .to_string();
+    };
+    let last = chars[first..]
+        .iter()
+        .position(|&c| c == MATCH_END)
+        .map_or(first + 1, |i| first + i + 1);
+    let ends_sentence = |c: char| matches!(c, '.' | '!' | '?');
+
+    let mut start = first;
+    while start > 0 {
+        let previous = chars[start - 1];
+        if previous == '\n' || (ends_sentence(previous) && chars[start].is_whitespace()) {
+            break;
+        }
+        start -= 1;
+    }
+
+    let mut end = last;
+    while end < chars.len() {
+        let c = chars[end];
+        if c == '\n' {
+            break;
+        }
+        if ends_sentence(c) && chars.get(end + 1).is_none_or(|next| next.is_whitespace()) {
+            end += 1;
+            break;
+        }
+        end += 1;
+    }
+
+    let mut clipped_start = false;
+    let mut clipped_end = false;
+    if end - start > max_chars {
+        let context = max_chars.saturating_sub(last - first);
+        let before = (context / 2).min(first - start);
+        clipped_start = first - before > start;
+        start = first - before;
+        let after = (context - before).min(end - last);
+        clipped_end = last + after < end;
+        end = last + after;
+    }
+
+    // A cut inside a later match would otherwise leave its tag unbalanced
+    let inside_match = |chars: &[char]| {
+        chars.iter().fold(false, |open, &c| match c {
+            MATCH_START => true,
+            MATCH_END => false,
+            _ => open,
+        })
+    };
+    let mut snippet = String::new();
+    if inside_match(&chars[..start]) {
+        snippet.push_str("<b>");
+    }
+    for &c in &chars[start..end] {
+        match c {
+            MATCH_START => snippet.push_str("<b>"),
+            MATCH_END => snippet.push_str("</b>"),
+            c => snippet.push(c),
+        }
+    }
+    if inside_match(&chars[..end]) {
+        snippet.push_str("</b>");
+    }
+
+    let snippet = snippet.trim();
+    format!(
+        "{}{}{}",
+        if clipped_start { "…" } else { "" },
+        snippet,
+        if clipped_end { "…" } else { "" }
+    )
+}
+
+/// Search the indexed text of every book, best matches first. Each match comes
+/// with the whole sentence around it, the matched words wrapped in `<b>`/`</b>`.
+#[tauri::command]
+pub fn search_library(
+    query: &str,
+    state: tauri::State<DbState>,
+) -> Result<Vec<ContentMatch>, AppError> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let conn = state.0.get()?;
+    // A wide snippet() window so only text near the match is read back, not
+    // the whole chapter, and the sentence is then cut out of it
+    let mut stmt = conn.prepare(
+        "SELECT book_id, chapter_href, snippet(book_content, 2, ?, ?, '…', ?)
+         FROM book_content
+         WHERE book_content MATCH ?
+            AND book_id NOT IN (SELECT id FROM books WHERE deleted_at IS NOT NULL)
+         ORDER BY bm25(book_content) LIMIT ?",
+    )?;
+
+    let rows = stmt.query_map(
+        params![
+            MATCH_START.to_string(),
+            MATCH_END.to_string(),
+            SENTENCE_SNIPPET_TOKENS,
+            escape_fts_query(query),
+            DEFAULT_SEARCH_LIMIT as i64
+        ],
+        |row| {
+            Ok(ContentMatch {
+                book_id: row.get(0)?,
+                chapter_href: row.get(1)?,
+                snippet: sentence_snippet(&row.get::<_, String>(2)?, SENTENCE_SNIPPET_MAX_CHARS),
+            })
+        },
+    )?;
+
+    let mut matches = Vec::new();
+    for row in rows {
+        matches.push(row?);
+    }
+
+    Ok(matches)
+}
+
+/// Passages shown per book by `lookup_in_context`
+const CONTEXT_SNIPPET_LIMIT: usize = 5;
+
+#[derive(Serialize)]
+pub struct LookupContext {
+    pub definitions: Vec<DictionaryEntry>,
+    /// Passages of the book containing the word; empty if it was never indexed
+    /// or the word doesn't occur in it
+    pub snippets: Vec<ContentMatch>,
+}
+
+/// Look a word up in the dictionary and find where it occurs in the open book's
+/// indexed text, so the definition can be shown next to the original passage
+#[tauri::command]
+pub fn lookup_in_context(
+    book_id: i64,
+    word: &str,
+    state: tauri::State<DbState>,
+) -> Result<LookupContext, AppError> {
+    let conn = state.0.get()?;
+    find_in_context(&conn, book_id, word)
+}
+
+/// The searches behind `lookup_in_context`
+fn find_in_context(conn: &Connection, book_id: i64, word: &str) -> Result<LookupContext, AppError> {
+    // Both searches see the word the way the dictionary does, so a selection
+    // like "Bank." finds the same passages it finds definitions for
+    let term = normalize_query(word);
+    let definitions = lookup_word(
+        conn,
+        &term,
+        None,
+        None,
+        false,
+        SearchMode::Default,
+        SearchField::Word,
+    )?
+    .results
+    .into_iter()
+    .map(|result| result.entry)
+    .collect();
+
+    if term.is_empty() {
+        return Ok(LookupContext {
+            definitions,
+            snippets: Vec::new(),
+        });
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT book_id, chapter_href, snippet(book_content, 2, '<b>', '</b>', '…', 16)
+         FROM book_content WHERE book_content MATCH ? AND book_id = ?
+         ORDER BY bm25(book_content) LIMIT ?",
+    )?;
+
+    let rows = stmt.query_map(
+        params![
+            escape_fts_query(&term),
+            book_id,
+            CONTEXT_SNIPPET_LIMIT as i64
+        ],
+        |row| {
+            Ok(ContentMatch {
+                book_id: row.get(0)?,
+                chapter_href: row.get(1)?,
+                snippet: row.get(2)?,
+            })
+        },
+    )?;
+
+    let mut snippets = Vec::new();
+    for row in rows {
+        snippets.push(row?);
+    }
+
+    Ok(LookupContext {
+        definitions,
+        snippets,
+    })
+}
+
+/// Most recently looked-up words, each listed once
+#[tauri::command]
+pub fn recent_lookups(limit: usize, state: tauri::State<DbState>) -> Result<Vec<String>, AppError> {
+    let conn = state.0.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT word FROM lookup_history
+         GROUP BY word ORDER BY MAX(id) DESC LIMIT ?",
+    )?;
+
+    let rows = stmt.query_map(params![limit as i64], |row| row.get::<_, String>(0))?;
+
+    let mut words = Vec::new();
+    for row in rows {
+        words.push(row?);
+    }
+
+    Ok(words)
+}
+
+/// Forget every dictionary lookup, returning how many were removed
+#[tauri::command]
+pub fn clear_lookup_history(state: tauri::State<DbState>) -> Result<usize, AppError> {
+    let conn = state.0.get()?;
+    Ok(conn.execute("DELETE FROM lookup_history", [])?)
+}
+
+/// Save a word to the glossary; saving it again is a no-op
+#[tauri::command]
+pub fn add_favorite(word: &str, state: tauri::State<DbState>) -> Result<(), AppError> {
+    let conn = state.0.get()?;
+    conn.execute(
+        "INSERT OR IGNORE INTO favorite_words (word) VALUES (?)",
+        params![normalize_query(word)],
+    )?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn remove_favorite(word: &str, state: tauri::State<DbState>) -> Result<(), AppError> {
+    let conn = state.0.get()?;
+    conn.execute(
+        "DELETE FROM favorite_words WHERE word = ?",
+        params![normalize_query(word)],
+    )?;
+
+    Ok(())
+}
+
+/// Favorite words with their definitions, most recently saved first
+#[tauri::command]
+pub fn list_favorites(state: tauri::State<DbState>) -> Result<Vec<DictionaryEntry>, AppError> {
+    let conn = state.0.get()?;
+    Ok(favorite_entries(&conn)?)
+}
+
+/// The query behind `list_favorites`
+fn favorite_entries(conn: &Connection) -> Result<Vec<DictionaryEntry>> {
+    // The MATCH (quoted like escape_fts_query) lets FTS5 use its index per
+    // favorite; headwords are then compared folded, the way lookups compare them
+    let mut stmt = conn.prepare(
+        "SELECT d.word, d.definition, d.part_of_speech, d.ipa FROM favorite_words f
+         JOIN dictionary d
+            ON d.word MATCH '\"' || replace(f.word, '\"', '\"\"') || '\"'
+                AND fold(d.word) = fold(f.word)
+         ORDER BY f.added_at DESC, f.word",
+    )?;
+
+    let rows = stmt.query_map([], entry_from_row)?;
+
+    let mut entries = Vec::new();
+    for row in rows {
+        entries.push(row?);
+    }
+
+    Ok(entries)
+}
+
+/// Remove every favorite word, returning how many were removed
+#[tauri::command]
+pub fn clear_favorites(state: tauri::State<DbState>) -> Result<usize, AppError> {
+    let conn = state.0.get()?;
+    Ok(conn.execute("DELETE FROM favorite_words", [])?)
+}
+
+/// Tables wiped by `clear_all_personal_data`
+const PERSONAL_DATA_TABLES: [&str; 5] = [
+    "lookup_history",
+    "favorite_words",
+    "bookmarks",
+    "highlights",
+    "reading_progress",
+];
+
+/// Forget lookups, favorites, bookmarks, highlights and reading progress in one
+/// transaction, keeping the books and dictionaries. Returns how many rows were
+/// removed in all.
+#[tauri::command]
+pub fn clear_all_personal_data(state: tauri::State<DbState>) -> Result<usize, AppError> {
+    let mut conn = state.0.get()?;
+    let tx = conn.transaction()?;
+    let mut removed = 0;
+    for table in PERSONAL_DATA_TABLES {
+        removed += tx.execute(&format!("DELETE FROM {}", table), [])?;
+    }
+    tx.commit()?;
+
+    Ok(removed)
+}
+
+/// Tag names are compared case-insensitively, so only surrounding space is trimmed
+fn tag_name(tag: &str) -> Result<&str, AppError> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        return Err(AppError::InvalidInput("Tag name is empty".to_string()));
+    }
+    Ok(tag)
+}
+
+/// Put a book on a shelf, creating the tag if it doesn't exist yet. Tagging a
+/// book twice with the same tag is a no-op.
+#[tauri::command]
+pub fn add_tag_to_book(
+    book_id: i64,
+    tag: &str,
+    state: tauri::State<DbState>,
+) -> Result<(), AppError> {
+    let tag = tag_name(tag)?;
+    let mut conn = state.0.get()?;
+    let tx = conn.transaction()?;
+
+    let book_exists: bool = tx.query_row(
+        "SELECT EXISTS(SELECT 1 FROM books WHERE id = ?)",
+        params![book_id],
+        |row| row.get(0),
+    )?;
+    if !book_exists {
+        return Err(AppError::NotFound(format!("Book {} not found", book_id)));
+    }
+
+    tx.execute("INSERT OR IGNORE INTO tags (name) VALUES (?)", params![tag])?;
+    tx.execute(
+        "INSERT OR IGNORE INTO book_tags (book_id, tag_id)
+         SELECT ?, id FROM tags WHERE name = ?",
+        params![book_id, tag],
+    )?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Take a book off a shelf. Tags left without books are deleted so the sidebar
+/// doesn't fill up with empty shelves.
+#[tauri::command]
+pub fn remove_tag_from_book(
+    book_id: i64,
+    tag: &str,
+    state: tauri::State<DbState>,
+) -> Result<(), AppError> {
+    let tag = tag_name(tag)?;
+    let mut conn = state.0.get()?;
+    let tx = conn.transaction()?;
+
+    tx.execute(
+        "DELETE FROM book_tags
+         WHERE book_id = ? AND tag_id = (SELECT id FROM tags WHERE name = ?)",
+        params![book_id, tag],
+    )?;
+    tx.execute(
+        "DELETE FROM tags
+         WHERE name = ? AND NOT EXISTS(SELECT 1 FROM book_tags WHERE tag_id = tags.id)",
+        params![tag],
+    )?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Books carrying a tag, most recently added first
+#[tauri::command]
+pub fn list_books_by_tag(tag: &str, state: tauri::State<DbState>) -> Result<Vec<Book>, AppError> {
+    let tag = tag_name(tag)?;
+    let conn = state.0.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM books
+         WHERE deleted_at IS NULL AND id IN (
+            SELECT bt.book_id FROM book_tags bt JOIN tags t ON t.id = bt.tag_id
+            WHERE t.name = ?
+         )
+         ORDER BY added_at DESC, id DESC",
+        BOOK_COLUMNS
+    ))?;
+
+    let rows = stmt.query_map(params![tag], book_from_row)?;
+
+    let mut books = Vec::new();
+    for row in rows {
+        books.push(row?);
+    }
+
+    Ok(books)
+}
+
+/// Every tag in use, alphabetically
+#[tauri::command]
+pub fn list_all_tags(state: tauri::State<DbState>) -> Result<Vec<String>, AppError> {
+    let conn = state.0.get()?;
+    let mut stmt = conn.prepare("SELECT name FROM tags ORDER BY name COLLATE NOCASE")?;
+
+    let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+
+    let mut tags = Vec::new();
+    for row in rows {
+        tags.push(row?);
+    }
+
+    Ok(tags)
+}
+
+/// A collection with the books filed directly in it and its subcollections
+#[derive(Serialize)]
+pub struct CollectionNode {
+    pub id: i64,
+    pub name: String,
+    pub book_ids: Vec<i64>,
+    pub children: Vec<CollectionNode>,
+}
+
+fn collection_exists(conn: &Connection, id: i64) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM collections WHERE id = ?)",
+        params![id],
+        |row| row.get(0),
+    )
+}
+
+/// Create a collection, at the top level or inside `parent_id`, and return its id
+#[tauri::command]
+pub fn create_collection(
+    name: &str,
+    parent_id: Option<i64>,
+    state: tauri::State<DbState>,
+) -> Result<i64, AppError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::InvalidInput(
+            "Collection name is empty".to_string(),
+        ));
+    }
+
+    let conn = state.0.get()?;
+    if let Some(parent_id) = parent_id {
+        if !collection_exists(&conn, parent_id)? {
+            return Err(AppError::NotFound(format!(
+                "Collection {} not found",
+                parent_id
+            )));
+        }
+    }
+    conn.execute(
+        "INSERT INTO collections (name, parent_id) VALUES (?, ?)",
+        params![name, parent_id],
+    )?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+/// Move a collection inside `parent_id`, or to the top level without one.
+/// Moving a collection inside itself or one of its own subcollections is refused.
+#[tauri::command]
+pub fn move_collection(
+    id: i64,
+    parent_id: Option<i64>,
+    state: tauri::State<DbState>,
+) -> Result<(), AppError> {
+    let conn = state.0.get()?;
+    if !collection_exists(&conn, id)? {
+        return Err(AppError::NotFound(format!("Collection {} not found", id)));
+    }
+
+    if let Some(parent_id) = parent_id {
+        if !collection_exists(&conn, parent_id)? {
+            return Err(AppError::NotFound(format!(
+                "Collection {} not found",
+                parent_id
+            )));
+        }
+        // Walk up from the new parent; reaching the collection means a cycle
+        let creates_cycle: bool = conn.query_row(
+            "WITH RECURSIVE ancestors(id) AS (
+                SELECT ?1
+                UNION
+                SELECT c.parent_id FROM collections c JOIN ancestors a ON c.id = a.id
+                WHERE c.parent_id IS NOT NULL
+             )
+             SELECT EXISTS(SELECT 1 FROM ancestors WHERE id = ?2)",
+            params![parent_id, id],
+            |row| row.get(0),
+        )?;
+        if creates_cycle {
+            return Err(AppError::InvalidInput(
+                "A collection can't be moved inside itself".to_string(),
+            ));
+        }
+    }
+
+    conn.execute(
+        "UPDATE collections SET parent_id = ? WHERE id = ?",
+        params![parent_id, id],
+    )?;
+
+    Ok(())
+}
+
+/// File a book in a collection. Adding a book that is already there is a no-op.
+#[tauri::command]
+pub fn add_book_to_collection(
+    collection_id: i64,
+    book_id: i64,
+    state: tauri::State<DbState>,
+) -> Result<(), AppError> {
+    let conn = state.0.get()?;
+    if !collection_exists(&conn, collection_id)? {
+        return Err(AppError::NotFound(format!(
+            "Collection {} not found",
+            collection_id
+        )));
+    }
+    let book_exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM books WHERE id = ?)",
+        params![book_id],
+        |row| row.get(0),
+    )?;
+    if !book_exists {
+        return Err(AppError::NotFound(format!("Book {} not found", book_id)));
+    }
+
+    conn.execute(
+        "INSERT OR IGNORE INTO collection_books (collection_id, book_id) VALUES (?, ?)",
+        params![collection_id, book_id],
+    )?;
+
+    Ok(())
+}
+
+/// Take the collections under `parent_id` out of `children` and attach their
+/// books and, recursively, their own subcollections
+fn collection_nodes(
+    parent_id: Option<i64>,
+    children: &mut HashMap<Option<i64>, Vec<(i64, String)>>,
+    book_ids: &mut HashMap<i64, Vec<i64>>,
+) -> Vec<CollectionNode> {
+    children
+        .remove(&parent_id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(id, name)| CollectionNode {
+            id,
+            name,
+            book_ids: book_ids.remove(&id).unwrap_or_default(),
+            children: collection_nodes(Some(id), children, book_ids),
+        })
+        .collect()
+}
+
+/// Every collection as a tree of top-level collections, siblings sorted by name
+#[tauri::command]
+pub fn list_collection_tree(state: tauri::State<DbState>) -> Result<Vec<CollectionNode>, AppError> {
+    let conn = state.0.get()?;
+
+    let mut book_ids: HashMap<i64, Vec<i64>> = HashMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT collection_id, book_id FROM collection_books
+         WHERE book_id IN (SELECT id FROM books WHERE deleted_at IS NULL)
+         ORDER BY book_id",
+    )?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    for row in rows {
+        let (collection_id, book_id) = row?;
+        book_ids.entry(collection_id).or_default().push(book_id);
+    }
+
+    let mut children: HashMap<Option<i64>, Vec<(i64, String)>> = HashMap::new();
+    let mut stmt = conn
+        .prepare("SELECT id, name, parent_id FROM collections ORDER BY name COLLATE NOCASE, id")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+    for row in rows {
+        let (id, name, parent_id) = row?;
+        children.entry(parent_id).or_default().push((id, name));
+    }
+
+    Ok(collection_nodes(None, &mut children, &mut book_ids))
+}
+
+/// Make a field safe for a tab-separated Anki import. Anki renders fields as HTML,
+/// so line breaks become `<br>`.
+fn anki_field(text: &str) -> String {
+    text.trim()
+        .replace("\r\n", "\n")
+        .replace(['\r', '\n'], "<br>")
+        .replace('\t', " ")
+}
+
+/// Export favorite words as Anki-importable TSV, one `word<TAB>definition` card
+/// per line. Words with several definitions get them joined with ` / `.
+#[tauri::command]
+pub fn export_favorites_anki(state: tauri::State<DbState>) -> Result<String, AppError> {
+    let conn = state.0.get()?;
+    Ok(favorites_tsv(&conn)?)
+}
+
+/// The export behind `export_favorites_anki`, joining favorites to headwords
+/// the same way `list_favorites` does and carding them under the headword
+fn favorites_tsv(conn: &Connection) -> Result<String> {
+    let mut stmt = conn.prepare(
+        "SELECT d.word, d.definition FROM favorite_words f
+         JOIN dictionary d
+            ON d.word MATCH '\"' || replace(f.word, '\"', '\"\"') || '\"'
+                AND fold(d.word) = fold(f.word)
+         ORDER BY f.added_at DESC, f.word, d.rowid",
+    )?;
+
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+
+    // Rows for the same word are adjacent, so cards can be built in one pass
+    let mut cards: Vec<(String, Vec<String>)> = Vec::new();
+    for row in rows {
+        let (word, definition) = row?;
+        match cards.last_mut() {
+            Some((last, definitions)) if *last == word => definitions.push(anki_field(&definition)),
+            _ => cards.push((word, vec![anki_field(&definition)])),
+        }
+    }
+
+    let mut tsv = String::new();
+    for (word, definitions) in cards {
+        tsv.push_str(&anki_field(&word));
+        tsv.push('\t');
+        tsv.push_str(&definitions.join(" / "));
+        tsv.push('\n');
+    }
+
+    Ok(tsv)
+}
+
+/// Progress at or above which a book counted as finished before books could be
+/// marked finished explicitly
+const FINISHED_PERCENT: f64 = 0.99;
+
+#[derive(Serialize)]
+pub struct ReadingStats {
+    pub total_books: i64,
+    pub books_finished: i64,
+    /// Books marked as finished during the current local calendar year
+    pub finished_this_year: i64,
+    pub total_highlights: i64,
+    pub total_bookmarks: i64,
+    /// Distinct words ever looked up in the dictionary
+    pub words_looked_up: i64,
+}
+
+/// Totals for the reading dashboard, counted in SQL in one round trip
+#[tauri::command]
+pub fn reading_stats(state: tauri::State<DbState>) -> Result<ReadingStats, AppError> {
+    let conn = state.0.get()?;
+    conn.query_row(
+        "SELECT
+            (SELECT count(*) FROM books WHERE deleted_at IS NULL),
+            (SELECT count(*) FROM books WHERE finished_at IS NOT NULL AND deleted_at IS NULL),
+            (SELECT count(*) FROM books
+             WHERE deleted_at IS NULL
+                AND strftime('%Y', finished_at, 'unixepoch', 'localtime')
+                    = strftime('%Y', 'now', 'localtime')),
+            (SELECT count(*) FROM highlights),
+            (SELECT count(*) FROM bookmarks),
+            (SELECT count(DISTINCT word) FROM lookup_history)",
+        [],
+        |row| {
+            Ok(ReadingStats {
+                total_books: row.get(0)?,
+                books_finished: row.get(1)?,
+                finished_this_year: row.get(2)?,
+                total_highlights: row.get(3)?,
+                total_bookmarks: row.get(4)?,
+                words_looked_up: row.get(5)?,
+            })
+        },
+    )
+    .map_err(AppError::from)
+}
+
+/// Totals over a book's finished reading sessions
+#[derive(Serialize)]
+pub struct SessionSummary {
+    pub sessions: i64,
+    pub total_seconds: i64,
+    pub words_read: i64,
+}
+
+/// Start timing a reading session and return its id
+#[tauri::command]
+pub fn start_session(book_id: i64, state: tauri::State<DbState>) -> Result<i64, AppError> {
+    let conn = state.0.get()?;
+    let inserted = conn.execute(
+        "INSERT INTO reading_sessions (book_id) SELECT id FROM books WHERE id = ?",
+        params![book_id],
+    )?;
+
+    if inserted == 0 {
+        return Err(AppError::NotFound(format!("Book {} not found", book_id)));
+    }
+    Ok(conn.last_insert_rowid())
+}
+
+/// Close a reading session, recording how many words were read during it
+#[tauri::command]
+pub fn end_session(
+    session_id: i64,
+    words_read: usize,
+    state: tauri::State<DbState>,
+) -> Result<(), AppError> {
+    let conn = state.0.get()?;
+    let ended_at: Option<Option<i64>> = conn
+        .query_row(
+            "SELECT ended_at FROM reading_sessions WHERE id = ?",
+            params![session_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    match ended_at {
+        None => {
+            return Err(AppError::NotFound(format!(
+                "Reading session {} not found",
+                session_id
+            )))
+        }
+        Some(Some(_)) => {
+            return Err(AppError::InvalidInput(format!(
+                "Reading session {} has already ended",
+                session_id
+            )))
+        }
+        Some(None) => {}
+    }
+
+    // Never end before the start, even if the system clock was set back meanwhile
+    conn.execute(
+        "UPDATE reading_sessions SET ended_at = max(unixepoch(), started_at), words_read = ?
+         WHERE id = ?",
+        params![words_read as i64, session_id],
+    )?;
+
+    Ok(())
+}
+
+/// Total time and words read across a book's finished sessions
+#[tauri::command]
+pub fn session_summary(
+    book_id: i64,
+    state: tauri::State<DbState>,
+) -> Result<SessionSummary, AppError> {
+    let conn = state.0.get()?;
+    conn.query_row(
+        "SELECT count(*), coalesce(sum(max(ended_at - started_at, 0)), 0), coalesce(sum(words_read), 0)
+         FROM reading_sessions WHERE book_id = ? AND ended_at IS NOT NULL",
+        params![book_id],
+        |row| {
+            Ok(SessionSummary {
+                sessions: row.get(0)?,
+                total_seconds: row.get(1)?,
+                words_read: row.get(2)?,
+            })
+        },
+    )
+    .map_err(AppError::from)
+}
+
+/// Set the number of minutes to read each day, or clear the goal with 0
+#[tauri::command]
+pub fn set_daily_goal(minutes: u32, state: tauri::State<DbState>) -> Result<(), AppError> {
+    let conn = state.0.get()?;
+    if minutes == 0 {
+        conn.execute("DELETE FROM reading_goals", [])?;
+    } else {
+        conn.execute(
+            "INSERT INTO reading_goals (id, daily_minutes) VALUES (1, ?)
+             ON CONFLICT(id) DO UPDATE SET
+                daily_minutes = excluded.daily_minutes,
+                updated_at = unixepoch()",
+            params![minutes],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Time read on one day against the daily goal
+#[derive(Serialize)]
+pub struct GoalProgress {
+    pub date: String,
+    pub seconds_read: i64,
+    /// `None` when no goal is set
+    pub target_minutes: Option<i64>,
+    pub met: bool,
+}
+
+/// How long was spent reading on `date` (`YYYY-MM-DD` in local time, today by
+/// default) compared with the daily goal. Sessions count towards the day they
+/// started on.
+#[tauri::command]
+pub fn goal_progress(
+    date: Option<String>,
+    state: tauri::State<DbState>,
+) -> Result<GoalProgress, AppError> {
+    let conn = state.0.get()?;
+    let date: String = match date {
+        Some(date) => {
+            conn.query_row(
+                "SELECT date(?1) WHERE date(?1) = ?1",
+                params![date],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| AppError::InvalidInput(format!("'{}' is not a YYYY-MM-DD date", date)))?
+        }
+        None => conn.query_row("SELECT date('now', 'localtime')", [], |row| row.get(0))?,
+    };
+
+    let seconds_read: i64 = conn.query_row(
+        "SELECT coalesce(sum(max(ended_at - started_at, 0)), 0) FROM reading_sessions
+         WHERE ended_at IS NOT NULL AND date(started_at, 'unixepoch', 'localtime') = ?",
+        params![date],
+        |row| row.get(0),
+    )?;
+    let target_minutes: Option<i64> = conn
+        .query_row("SELECT daily_minutes FROM reading_goals", [], |row| {
+            row.get(0)
+        })
+        .optional()?;
+
+    Ok(GoalProgress {
+        date,
+        seconds_read,
+        target_minutes,
+        met: target_minutes.is_some_and(|minutes| seconds_read >= minutes * 60),
+    })
+}
+
+fn preference(conn: &Connection, key: &str) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT value FROM preferences WHERE key = ?",
+        params![key],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Read a stored preference, or `None` if it was never set
+#[tauri::command]
+pub fn get_preference(key: &str, state: tauri::State<DbState>) -> Result<Option<String>, AppError> {
+    let conn = state.0.get()?;
+    preference(&conn, key).map_err(AppError::from)
+}
+
+/// Store a preference, replacing any earlier value
+#[tauri::command]
+pub fn set_preference(
+    key: &str,
+    value: &str,
+    state: tauri::State<DbState>,
+) -> Result<(), AppError> {
+    let conn = state.0.get()?;
+    conn.execute(
+        "INSERT INTO preferences (key, value) VALUES (?, ?)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = unixepoch()",
+        params![key, value],
+    )?;
+
+    Ok(())
+}
+
+/// Read a preference stored as `true`/`false` (or `1`/`0`)
+#[tauri::command]
+pub fn get_pref_bool(key: &str, state: tauri::State<DbState>) -> Result<Option<bool>, AppError> {
+    let conn = state.0.get()?;
+    preference(&conn, key)?
+        .map(|value| match value.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" => Ok(true),
+            "false" | "0" => Ok(false),
+            _ => Err(AppError::InvalidFormat(format!(
+                "Preference '{}' is not a boolean: {}",
+                key, value
+            ))),
+        })
+        .transpose()
+}
+
+/// Read a preference stored as a whole number
+#[tauri::command]
+pub fn get_pref_int(key: &str, state: tauri::State<DbState>) -> Result<Option<i64>, AppError> {
+    let conn = state.0.get()?;
+    preference(&conn, key)?
+        .map(|value| {
+            value.trim().parse().map_err(|_| {
+                AppError::InvalidFormat(format!(
+                    "Preference '{}' is not an integer: {}",
+                    key, value
+                ))
+            })
+        })
+        .transpose()
+}
+
+/// Tables holding rows that belong to a book, all deleted along with it by
+/// `ON DELETE CASCADE`
+const BOOK_CHILD_TABLES: [&str; 7] = [
+    "reading_progress",
+    "reading_sessions",
+    "book_settings",
+    "bookmarks",
+    "highlights",
+    "book_tags",
+    "collection_books",
+];
+
+/// Move a book to the trash, hiding it from the library until it's restored
+/// or the trash is emptied. Returns how many progress, session, settings,
+/// bookmark, highlight, tag and collection rows belong to it and would go with
+/// it, so the UI can say what emptying the trash will remove.
+#[tauri::command]
+pub fn delete_book(book_id: i64, state: tauri::State<DbState>) -> Result<usize, AppError> {
+    let mut conn = state.0.get()?;
+    let tx = conn.transaction()?;
+
+    // A book trashed twice keeps its original deletion time
+    let updated = tx.execute(
+        "UPDATE books SET deleted_at = COALESCE(deleted_at, unixepoch()) WHERE id = ?",
+        params![book_id],
+    )?;
+    if updated == 0 {
+        return Err(AppError::NotFound(format!("Book {} not found", book_id)));
+    }
+
+    let mut related = 0;
+    for table in BOOK_CHILD_TABLES {
+        let count: i64 = tx.query_row(
+            &format!("SELECT count(*) FROM {} WHERE book_id = ?", table),
+            params![book_id],
+            |row| row.get(0),
+        )?;
+        related += count as usize;
+    }
+    tx.commit()?;
+
+    Ok(related)
+}
+
+/// Take a book back out of the trash
+#[tauri::command]
+pub fn restore_book(book_id: i64, state: tauri::State<DbState>) -> Result<(), AppError> {
+    let conn = state.0.get()?;
+    let updated = conn.execute(
+        "UPDATE books SET deleted_at = NULL WHERE id = ? AND deleted_at IS NOT NULL",
+        params![book_id],
+    )?;
+
+    if updated == 0 {
+        return Err(AppError::NotFound(format!(
+            "Book {} is not in the trash",
+            book_id
+        )));
+    }
+
+    Ok(())
+}
+
+/// Books in the trash, most recently deleted first
+#[tauri::command]
+pub fn list_trash(state: tauri::State<DbState>) -> Result<Vec<Book>, AppError> {
+    let conn = state.0.get()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM books WHERE deleted_at IS NOT NULL
+         ORDER BY deleted_at DESC, id DESC",
+        BOOK_COLUMNS
+    ))?;
+
+    let rows = stmt.query_map([], book_from_row)?;
+
+    let mut books = Vec::new();
+    for row in rows {
+        books.push(row?);
+    }
+
+    Ok(books)
+}
+
+/// Permanently remove every book in the trash together with its progress,
+/// sessions, settings, bookmarks, highlights, tag and collection links and
+/// indexed text, optionally deleting the cached covers too. Returns how many
+/// books were removed.
+#[tauri::command]
+pub fn empty_trash(
+    delete_covers: Option<bool>,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+) -> Result<usize, AppError> {
+    let mut conn = state.0.get()?;
+    let tx = conn.transaction()?;
+
+    let hashes: Vec<Option<String>> = tx
+        .prepare("SELECT file_hash FROM books WHERE deleted_at IS NOT NULL")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+
+    // The content index is FTS5 and can't take part in foreign keys
+    tx.execute(
+        "DELETE FROM book_content
+         WHERE book_id IN (SELECT id FROM books WHERE deleted_at IS NOT NULL)",
+        [],
+    )?;
+    let removed = tx.execute("DELETE FROM books WHERE deleted_at IS NOT NULL", [])?;
+    // Match remove_tag_from_book, which never leaves empty shelves behind
+    tx.execute(
+        "DELETE FROM tags WHERE NOT EXISTS(SELECT 1 FROM book_tags WHERE tag_id = tags.id)",
+        [],
+    )?;
+    tx.commit()?;
+
+    if delete_covers.unwrap_or(false) {
+        let covers_dir = covers_dir(&app_handle)?;
+        for hash in hashes.into_iter().flatten() {
+            remove_cached_cover(&covers_dir, &hash)?;
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Format version written by `export_library`; newer backups are refused
+const LIBRARY_BACKUP_VERSION: u32 = 1;
+
+/// A tag on a book, as stored in a library backup
+#[derive(Serialize, Deserialize)]
+pub struct BookTag {
+    pub book_id: i64,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FavoriteWord {
+    pub word: String,
+    pub added_at: i64,
+}
+
+/// Everything `export_library` backs up. Ids are only meaningful within one
+/// backup; imported rows get fresh ids.
+#[derive(Serialize, Deserialize)]
+pub struct LibraryBackup {
+    pub version: u32,
+    pub books: Vec<Book>,
+    #[serde(default)]
+    pub progress: Vec<Progress>,
+    #[serde(default)]
+    pub bookmarks: Vec<Bookmark>,
+    #[serde(default)]
+    pub highlights: Vec<Highlight>,
+    #[serde(default)]
+    pub tags: Vec<BookTag>,
+    #[serde(default)]
+    pub favorites: Vec<FavoriteWord>,
+}
+
+/// Back up the library's books, reading progress, bookmarks, highlights, tags
+/// and favorite words as a JSON document for `import_library`
+#[tauri::command]
+pub fn export_library(state: tauri::State<DbState>) -> Result<String, AppError> {
+    let conn = state.0.get()?;
+    serde_json::to_string_pretty(&library_backup(&conn)?)
+        .map_err(|e| AppError::InvalidFormat(format!("Could not serialize library: {}", e)))
+}
+
+fn library_backup(conn: &Connection) -> Result<LibraryBackup> {
+    let books = conn
+        .prepare(&format!("SELECT {} FROM books ORDER BY id", BOOK_COLUMNS))?
+        .query_map([], book_from_row)?
+        .collect::<Result<_>>()?;
+
+    let progress = conn
+        .prepare("SELECT book_id, location, percent, updated_at FROM reading_progress")?
+        .query_map([], progress_from_row)?
+        .collect::<Result<_>>()?;
+
+    let bookmarks = conn
+        .prepare(&format!(
+            "SELECT {} FROM bookmarks ORDER BY id",
+            BOOKMARK_COLUMNS
+        ))?
+        .query_map([], bookmark_from_row)?
+        .collect::<Result<_>>()?;
+
+    let highlights = conn
+        .prepare(&format!(
+            "SELECT {} FROM highlights h ORDER BY h.id",
+            HIGHLIGHT_COLUMNS
+        ))?
+        .query_map([], highlight_from_row)?
+        .collect::<Result<_>>()?;
+
+    let tags = conn
+        .prepare(
+            "SELECT bt.book_id, t.name FROM book_tags bt JOIN tags t ON t.id = bt.tag_id
+             ORDER BY bt.book_id, t.name",
+        )?
+        .query_map([], |row| {
+            Ok(BookTag {
+                book_id: row.get(0)?,
+                name: row.get(1)?,
+            })
+        })?
+        .collect::<Result<_>>()?;
+
+    let favorites = conn
+        .prepare("SELECT word, added_at FROM favorite_words ORDER BY added_at, word")?
+        .query_map([], |row| {
+            Ok(FavoriteWord {
+                word: row.get(0)?,
+                added_at: row.get(1)?,
+            })
+        })?
+        .collect::<Result<_>>()?;
+
+    Ok(LibraryBackup {
+        version: LIBRARY_BACKUP_VERSION,
+        books,
+        progress,
+        bookmarks,
+        highlights,
+        tags,
+        favorites,
+    })
+}
+
+/// Restore a backup made by `export_library` in a single transaction. A book
+/// whose file is already in the library (by hash, or by path for books never
+/// hashed) is skipped together with its progress, bookmarks, highlights and
+/// tags, so importing the same backup twice is harmless; favorite words already
+/// saved are skipped too.
+#[tauri::command]
+pub fn import_library(
+    json: String,
+    state: tauri::State<DbState>,
+) -> Result<ImportResult, AppError> {
+    let backup: LibraryBackup = serde_json::from_str(&json)
+        .map_err(|e| AppError::InvalidFormat(format!("Invalid library backup: {}", e)))?;
+    if backup.version > LIBRARY_BACKUP_VERSION {
+        return Err(AppError::InvalidFormat(format!(
+            "Library backup version {} is newer than this app supports",
+            backup.version
+        )));
+    }
+
+    let mut conn = state.0.get()?;
+    restore_library(&mut conn, &backup).map_err(AppError::from)
+}
+
+fn restore_library(conn: &mut Connection, backup: &LibraryBackup) -> Result<ImportResult> {
+    let tx = conn.transaction()?;
+    let mut imported = 0;
+    let mut skipped = 0;
+
+    // Ids in the backup mapped to the ids the imported books were given
+    let mut book_ids = HashMap::new();
+    for book in &backup.books {
+        // Books added before hashing have no hash to collide on, so match those by path
+        if book.file_hash.is_none() {
+            let exists: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM books WHERE file_hash IS NULL AND file_path = ?)",
+                params![book.file_path],
+                |row| row.get(0),
+            )?;
+            if exists {
+                skipped += 1;
+                continue;
+            }
+        }
+        let inserted = tx.execute(
+            "INSERT INTO books (
+                title, author, file_path, file_hash, added_at, last_opened, finished_at, deleted_at
+             )
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(file_hash) DO NOTHING",
+            params![
+                book.title,
+                book.author,
+                book.file_path,
+                book.file_hash,
+                book.added_at,
+                book.last_opened,
+                book.finished_at,
+                book.deleted_at
+            ],
+        )?;
+        if inserted > 0 {
+            book_ids.insert(book.id, tx.last_insert_rowid());
+            imported += 1;
+        } else {
+            skipped += 1;
+        }
+    }
+
+    for progress in &backup.progress {
+        let Some(book_id) = book_ids.get(&progress.book_id) else {
+            skipped += 1;
+            continue;
+        };
+        tx.execute(
+            "INSERT INTO reading_progress (book_id, location, percent, updated_at)
+             VALUES (?, ?, ?, ?)",
+            params![
+                book_id,
+                progress.location,
+                progress.percent,
+                progress.updated_at
+            ],
+        )?;
+        imported += 1;
+    }
+
+    for bookmark in &backup.bookmarks {
+        let Some(book_id) = book_ids.get(&bookmark.book_id) else {
+            skipped += 1;
+            continue;
+        };
+        tx.execute(
+            "INSERT INTO bookmarks (book_id, location, label, note, created_at)
+             VALUES (?, ?, ?, ?, ?)",
+            params![
+                book_id,
+                bookmark.location,
+                bookmark.label,
+                bookmark.note,
+                bookmark.created_at
+            ],
+        )?;
+        imported += 1;
+    }
+
+    for highlight in &backup.highlights {
+        let Some(book_id) = book_ids.get(&highlight.book_id) else {
+            skipped += 1;
+            continue;
+        };
+        tx.execute(
+            "INSERT INTO highlights (book_id, location, text, color, note, created_at)
+             VALUES (?, ?, ?, ?, ?, ?)",
+            params![
+                book_id,
+                highlight.location,
+                highlight.text,
+                highlight.color,
+                highlight.note,
+                highlight.created_at
+            ],
+        )?;
+        imported += 1;
+    }
+
+    for tag in &backup.tags {
+        let (Some(book_id), Ok(name)) = (book_ids.get(&tag.book_id), tag_name(&tag.name)) else {
+            skipped += 1;
+            continue;
+        };
+        tx.execute(
+            "INSERT INTO tags (name) VALUES (?) ON CONFLICT(name) DO NOTHING",
+            params![name],
+        )?;
+        tx.execute(
+            "INSERT OR IGNORE INTO book_tags (book_id, tag_id)
+             SELECT ?, id FROM tags WHERE name = ?",
+            params![book_id, name],
+        )?;
+        imported += 1;
+    }
+
+    for favorite in &backup.favorites {
+        let inserted = tx.execute(
+            "INSERT INTO favorite_words (word, added_at) VALUES (?, ?)
+             ON CONFLICT(word) DO NOTHING",
+            params![favorite.word, favorite.added_at],
+        )?;
+        if inserted > 0 {
+            imported += 1;
+        } else {
+            skipped += 1;
+        }
+    }
+
+    tx.commit()?;
+
+    Ok(ImportResult { imported, skipped })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh in-memory database with the schema and the fallback dictionary
+    fn test_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        register_functions(&conn).unwrap();
+        init_schema(&conn, None).unwrap();
+        conn
+    }
+
+    #[test]
+    fn complete_word_matches_prefixes() {
+        let conn = test_db();
+        assert!(complete_word(&conn, "comp", 10)
+            .unwrap()
+            .contains(&"compiler".to_string()));
+        assert!(!complete_word(&conn, "c", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn complete_word_escapes_fts_syntax() {
+        let conn = test_db();
+        assert!(complete_word(&conn, "c++", 10).is_ok());
+        assert!(complete_word(&conn, "\"", 10).is_ok());
+    }
+
+    /// A headword search the way the bubble runs it
+    fn search(conn: &Connection, word: &str) -> Result<SearchResponse, AppError> {
+        lookup_word(
+            conn,
+            word,
+            None,
+            None,
+            false,
+            SearchMode::Default,
+            SearchField::Word,
+        )
+    }
+
+    #[test]
+    fn lookup_word_escapes_fts_syntax() {
+        let conn = test_db();
+        for query in ["\"", "say \"hi\"", "well-known", "-", "c++", "a:b", "*"] {
+            assert!(search(&conn, query).is_ok(), "query {:?} failed", query);
+        }
+    }
+
+    #[test]
+    fn lookup_word_with_empty_query_finds_nothing() {
+        let conn = test_db();
+        for query in ["", "   "] {
+            let response = search(&conn, query).unwrap();
+            assert!(response.results.is_empty());
+            assert!(response.suggestions.is_empty());
+        }
+    }
+
+    #[test]
+    fn lookup_word_ranks_more_relevant_definitions_first() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO dictionary (word, definition) VALUES
+                ('orchestra', 'A large group of musicians, led by a conductor, who play classical music together on many instruments, sometimes with a band.'),
+                ('ribbon', 'A band of cloth; a narrow band used for tying.');",
+        )
+        .unwrap();
+
+        let response = lookup_word(
+            &conn,
+            "band",
+            None,
+            None,
+            false,
+            SearchMode::Default,
+            SearchField::Definition,
+        )
+        .unwrap();
+        let words: Vec<&str> = response
+            .results
+            .iter()
+            .map(|result| result.entry.word.as_str())
+            .collect();
+        assert_eq!(words, ["ribbon", "orchestra"]);
+        assert!(response.results[0].score > response.results[1].score);
+    }
+
+    #[test]
+    fn insert_book_keeps_one_row_per_file_and_restores_trashed_books() {
+        let conn = test_db();
+        let dir = std::env::temp_dir();
+        let paths = [
+            dir.join(format!("open_read_book_a_{}.txt", std::process::id())),
+            dir.join(format!("open_read_book_b_{}.txt", std::process::id())),
+        ];
+        let hashes: Vec<String> = paths
+            .iter()
+            .map(|path| {
+                fs::write(path, "The same book, copied twice.").unwrap();
+                let hash = hash_file(path, HashAlgorithm::Sha256).unwrap();
+                fs::remove_file(path).unwrap();
+                hash
+            })
+            .collect();
+        let count = |conn: &Connection| -> i64 {
+            conn.query_row("SELECT COUNT(*) FROM books", [], |row| row.get(0))
+                .unwrap()
+        };
+
+        let first = insert_book(&conn, "a.txt", "Copy", None, &hashes[0]).unwrap();
+        let second = insert_book(&conn, "b.txt", "Copy", None, &hashes[1]).unwrap();
+        assert!(!first.was_duplicate);
+        assert!(second.was_duplicate);
+        assert_eq!(second.id, first.id);
+        assert_eq!(count(&conn), 1);
+
+        conn.execute(
+            "UPDATE books SET deleted_at = unixepoch() WHERE id = ?",
+            params![first.id],
+        )
+        .unwrap();
+        let restored = insert_book(&conn, "a.txt", "Copy", None, &hashes[0]).unwrap();
+        assert!(restored.was_duplicate);
+        assert_eq!(restored.id, first.id);
+        assert_eq!(count(&conn), 1);
+        let deleted_at: Option<i64> = conn
+            .query_row(
+                "SELECT deleted_at FROM books WHERE id = ?",
+                params![first.id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(deleted_at, None);
+    }
+
+    #[test]
+    fn seeding_in_one_transaction_beats_autocommit() {
+        let path = std::env::temp_dir().join(format!("open_read_seed_{}.db", std::process::id()));
+        let conn = Connection::open(&path).unwrap();
+        register_functions(&conn).unwrap();
+        init_schema(&conn, None).unwrap();
+
+        let entries: Vec<DictionaryEntry> = (0..2_000)
+            .map(|i| DictionaryEntry {
+                word: format!("word{}", i),
+                definition: format!("Generated definition number {} for timing the seed.", i),
+                part_of_speech: Some("noun".to_string()),
+                ipa: None,
+            })
+            .collect();
+
+        let started = std::time::Instant::now();
+        for entry in &entries {
+            conn.execute(
+                "INSERT INTO dictionary (word, definition, source, part_of_speech, ipa)
+                 VALUES (?, ?, ?, ?, ?)",
+                params![
+                    entry.word,
+                    entry.definition,
+                    BUNDLED_DICTIONARY_SOURCE,
+                    entry.part_of_speech,
+                    entry.ipa
+                ],
+            )
+            .unwrap();
+        }
+        let autocommit = started.elapsed();
+        conn.execute("DELETE FROM dictionary", []).unwrap();
+
+        let started = std::time::Instant::now();
+        seed_dictionary(&conn, &entries).unwrap();
+        let transaction = started.elapsed();
+
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM dictionary", [], |row| row.get(0))
+            .unwrap();
+        drop(conn);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+
+        assert_eq!(count, 2_000);
+        assert!(
+            transaction * 5 < autocommit,
+            "one transaction took {:?}, autocommit took {:?}",
+            transaction,
+            autocommit
+        );
+    }
+
+    #[test]
+    fn lookup_word_ignores_accents_and_case() {
+        let conn = test_db();
+        conn.execute(
+            "INSERT INTO dictionary (word, definition) VALUES ('café', 'A small restaurant.')",
+            [],
+        )
+        .unwrap();
+
+        for query in ["cafe", "café", "CAFE", "Café"] {
+            let response = search(&conn, query).unwrap();
+            let words: Vec<&str> = response
+                .results
+                .iter()
+                .map(|result| result.entry.word.as_str())
+                .collect();
+            assert_eq!(words, ["café"], "query {:?}", query);
+        }
+        let response = search(&conn, "COMPILER").unwrap();
+        assert_eq!(response.results[0].entry.word, "compiler");
+    }
+
+    #[test]
+    fn lookup_word_ranks_the_exact_headword_first() {
+        let conn = test_db();
+        // "run out" mentions run more often, so BM25 alone would put it first
+        conn.execute_batch(
+            "INSERT INTO dictionary (word, definition) VALUES
+                ('run', 'To move quickly on foot.'),
+                ('run out', 'To run short; when supplies run low they run out.');",
+        )
+        .unwrap();
+
+        let response = lookup_word(
+            &conn,
+            "run",
+            None,
+            None,
+            false,
+            SearchMode::Prefix,
+            SearchField::Word,
+        )
+        .unwrap();
+        let words: Vec<&str> = response
+            .results
+            .iter()
+            .map(|result| result.entry.word.as_str())
+            .collect();
+        assert_eq!(words, ["run", "run out"]);
+        assert_eq!(response.results[0].score, 1.0);
+        assert!(response.results[1].score <= 1.0);
+    }
+
+    #[test]
+    fn database_size_counts_pages_still_in_the_wal() {
+        let path = std::env::temp_dir().join(format!("open_read_size_{}.db", std::process::id()));
+        let conn = Connection::open(&path).unwrap();
+        register_functions(&conn).unwrap();
+        init_schema(&conn, None).unwrap();
+        let before = database_size(&conn).unwrap();
+
+        let entries: Vec<DictionaryEntry> = (0..500)
+            .map(|i| DictionaryEntry {
+                word: format!("word{}", i),
+                definition: "A definition long enough to fill some pages. ".repeat(20),
+                part_of_speech: None,
+                ipa: None,
+            })
+            .collect();
+        insert_entries(&conn, &entries).unwrap();
+        let wal = fs::metadata(format!("{}-wal", path.display()))
+            .unwrap()
+            .len();
+        let after = database_size(&conn).unwrap();
+        let file = fs::metadata(&path).unwrap().len();
+        let wal_after = fs::metadata(format!("{}-wal", path.display()))
+            .unwrap()
+            .len();
+        drop(conn);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+
+        assert!(wal > 0);
+        assert_eq!(wal_after, wal, "measuring must not checkpoint the WAL");
+        assert!(after > before);
+        assert_eq!(after, file + wal);
+    }
+
+    #[test]
+    fn merge_duplicates_keeps_the_first_rowid() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO dictionary (word, definition, source) VALUES
+                ('lexeme', 'A unit of meaning.', 'test'),
+                ('lexeme', 'A unit of meaning.', 'test'),
+                ('lexeme', 'An abstract word form.', 'test');",
+        )
+        .unwrap();
+        let first: i64 = conn
+            .query_row(
+                "SELECT min(rowid) FROM dictionary WHERE word = 'lexeme'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+
+        assert_eq!(merge_duplicates(&conn).unwrap(), 2);
+        let rows: Vec<(i64, String)> = conn
+            .prepare("SELECT rowid, definition FROM dictionary WHERE word = 'lexeme'")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            [(
+                first,
+                format!(
+                    "A unit of meaning.{}An abstract word form.",
+                    MERGED_DEFINITION_SEPARATOR
+                )
+            )]
+        );
+        let search = |word: &str| -> i64 {
+            conn.query_row(
+                "SELECT COUNT(*) FROM dictionary WHERE dictionary MATCH ?",
+                params![word],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(search("abstract"), 1);
+    }
+
+    #[test]
+    fn find_in_context_normalizes_the_selection() {
+        let conn = test_db();
+        conn.execute(
+            "INSERT INTO book_content (book_id, chapter_href, text)
+             VALUES (1, 'ch1.xhtml', 'The compiler rejected the program.')",
+            [],
+        )
+        .unwrap();
+
+        let context = find_in_context(&conn, 1, " Compiler, ").unwrap();
+        assert_eq!(context.definitions[0].word, "compiler");
+        assert_eq!(context.snippets.len(), 1);
+        assert!(context.snippets[0].snippet.contains("<b>compiler</b>"));
+        assert!(find_in_context(&conn, 2, "compiler")
+            .unwrap()
+            .snippets
+            .is_empty());
+    }
+
+    #[test]
+    fn import_calibre_library_skips_known_paths_and_reports_missing_books() {
+        let dir = std::env::temp_dir().join(format!("open_read_calibre_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for folder in ["Ann Author/Present (1)", "Ann Author/Again (2)"] {
+            fs::create_dir_all(dir.join(folder)).unwrap();
+        }
+        fs::write(
+            dir.join("Ann Author/Present (1)/Present.txt"),
+            "First book.",
+        )
+        .unwrap();
+        fs::write(dir.join("Ann Author/Again (2)/Again.txt"), "Second book.").unwrap();
+
+        let calibre = Connection::open(dir.join("metadata.db")).unwrap();
+        calibre
+            .execute_batch(
+                "CREATE TABLE books (id INTEGER PRIMARY KEY, title TEXT, path TEXT, timestamp TEXT);
+                 CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT);
+                 CREATE TABLE books_authors_link (id INTEGER PRIMARY KEY, book INTEGER, author INTEGER);
+                 CREATE TABLE data (id INTEGER PRIMARY KEY, book INTEGER, format TEXT, name TEXT);
+                 INSERT INTO authors VALUES (1, 'Ann Author');
+                 INSERT INTO books VALUES
+                     (1, 'Present', 'Ann Author/Present (1)', '2020-01-02 03:04:05+00:00'),
+                     (2, 'Again', 'Ann Author/Again (2)', '2021-01-02 03:04:05+00:00'),
+                     (3, 'Gone', 'Ann Author/Gone (3)', NULL);
+                 INSERT INTO books_authors_link VALUES (1, 1, 1), (2, 2, 1), (3, 3, 1);
+                 INSERT INTO data VALUES
+                     (1, 1, 'TXT', 'Present'), (2, 2, 'TXT', 'Again'), (3, 3, 'EPUB', 'Gone');",
+            )
+            .unwrap();
+        drop(calibre);
+
+        let pool = init_db(None).unwrap();
+        let known = dir.join("Ann Author/Again (2)/Again.txt");
+        insert_book(
+            &pool.get().unwrap(),
+            &known.to_string_lossy(),
+            "Again",
+            None,
+            "a hash the file doesn't have",
+        )
+        .unwrap();
+
+        let mut progress = Vec::new();
+        let summary = import_calibre_library(&pool, &dir.join("metadata.db"), |event| {
+            progress.push((event.current, event.total))
+        });
+        let (title, author, added_at): (String, Option<String>, i64) = pool
+            .get()
+            .unwrap()
+            .query_row(
+                "SELECT title, author, added_at FROM books WHERE file_path LIKE '%Present.txt'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let summary = summary.unwrap();
+        assert_eq!(summary.imported, 1);
+        assert_eq!(summary.duplicates, 1);
+        assert_eq!(summary.missing, ["Gone"]);
+        assert!(summary.errors.is_empty());
+        assert_eq!(progress, [(1, 3), (2, 3), (3, 3)]);
+        assert_eq!(title, "Present");
+        assert_eq!(author.as_deref(), Some("Ann Author"));
+        assert_eq!(added_at, 1_577_934_245);
+    }
+
+    /// `sentence_snippet` of `text` with `[`/`]` standing in for the markers
+    fn snippet_of(text: &str, max_chars: usize) -> String {
+        let marked = text
+            .replace('[', &MATCH_START.to_string())
+            .replace(']', &MATCH_END.to_string());
+        sentence_snippet(&marked, max_chars)
+    }
+
+    #[test]
+    fn sentence_snippet_cuts_out_the_matching_sentence() {
+        assert_eq!(
+            snippet_of(
+                "It was late. The [compiler] failed again! Nobody knew why.",
+                300
+            ),
+            "The <b>compiler</b> failed again!"
+        );
+        assert_eq!(
+            snippet_of("First line\nA [match] here\nLast line", 300),
+            "A <b>match</b> here"
+        );
+    }
+
+    #[test]
+    fn sentence_snippet_without_terminators_keeps_the_text_to_either_end() {
+        assert_eq!(
+            snippet_of("no stop before the [word]", 300),
+            "no stop before the <b>word</b>"
+        );
+        assert_eq!(
+            snippet_of("[word] and nothing after", 300),
+            "<b>word</b> and nothing after"
+        );
+        assert_eq!(snippet_of("no match at all", 5), "no ma");
+    }
+
+    #[test]
+    fn sentence_snippet_trims_long_sentences_around_the_match() {
+        let text = format!("{} [needle] {}", "a ".repeat(200), "b ".repeat(200));
+        let snippet = snippet_of(&text, 40);
+        assert!(
+            snippet.starts_with('…') && snippet.ends_with('…'),
+            "{}",
+            snippet
+        );
+        assert!(snippet.contains("<b>needle</b>"));
+        assert!(snippet.chars().count() <= 40 + "<b></b>".len() + 2);
+
+        // The cap falls inside a later match, whose tag must still close
+        let text = format!("x [near] {}[other match] tail", "y ".repeat(10));
+        let snippet = snippet_of(&text, 30);
+        assert_eq!(
+            snippet.matches("<b>").count(),
+            snippet.matches("</b>").count()
+        );
+        assert!(snippet.contains("<b>near</b>"));
+    }
+
+    #[test]
+    fn sentence_snippet_counts_characters_not_bytes() {
+        assert_eq!(
+            snippet_of("Ça va. Un café [noir] très fort ! Fin.", 300),
+            "Un café <b>noir</b> très fort !"
+        );
+        let snippet = snippet_of(&format!("{}[é]{}", "ü".repeat(100), "ö".repeat(100)), 11);
+        assert_eq!(snippet, "…üüüü<b>é</b>öööö…");
+    }
+
+    #[test]
+    fn sentence_snippet_ignores_terminators_inside_the_match() {
+        assert_eq!(
+            snippet_of("Hello. Ask [Mr. Smith] today. Bye.", 300),
+            "Ask <b>Mr. Smith</b> today."
+        );
+        assert_eq!(
+            snippet_of("Stop! [Wait!] Go? Now.", 300),
+            "<b>Wait!</b> Go?"
+        );
+        assert_eq!(snippet_of("Well. [Why?] then.", 300), "<b>Why?</b> then.");
+    }
+
+    #[test]
+    fn favorites_match_headwords_ignoring_accents_and_case() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO dictionary (word, definition) VALUES ('café', 'A small restaurant.');
+             INSERT INTO favorite_words (word) VALUES ('cafe'), ('Compiler');",
+        )
+        .unwrap();
+
+        let mut words: Vec<String> = favorite_entries(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.word)
+            .collect();
+        words.sort();
+        assert_eq!(words, ["café", "compiler"]);
+    }
+
+    #[test]
+    fn favorites_tsv_exports_accented_headwords() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO dictionary (word, definition) VALUES
+                ('café', 'A small restaurant.'),
+                ('café', 'Coffee.');
+             INSERT INTO favorite_words (word) VALUES ('cafe');",
+        )
+        .unwrap();
+
+        assert_eq!(
+            favorites_tsv(&conn).unwrap(),
+            "café\tA small restaurant. / Coffee.\n"
+        );
+    }
+
+    fn spine() -> Vec<String> {
+        ["Text/cover.xhtml", "Text/ch1.xhtml", "Text/ch2.xhtml"]
+            .map(String::from)
+            .to_vec()
+    }
+
+    #[test]
+    fn spine_position_ignores_fragments() {
+        assert_eq!(
+            spine_position("Text/ch1.xhtml#section-2", &spine()),
+            Some(1)
+        );
+        assert_eq!(spine_position("Text/ch2.xhtml#", &spine()), Some(2));
+        assert_eq!(spine_position("Text/cover.xhtml", &spine()), Some(0));
+    }
+
+    #[test]
+    fn spine_position_matches_hrefs_relative_to_the_opf() {
+        // The reader may report paths from the archive root or bare file names
+        assert_eq!(spine_position("OEBPS/Text/ch2.xhtml#p3", &spine()), Some(2));
+        assert_eq!(spine_position("ch1.xhtml", &spine()), Some(1));
+        // Only whole path segments match
+        assert_eq!(spine_position("xch1.xhtml", &spine()), None);
+    }
+
+    #[test]
+    fn spine_position_reads_cfi_spine_steps() {
+        assert_eq!(spine_position("epubcfi(/6/4!/4/2/1:0)", &spine()), Some(1));
+        assert_eq!(spine_position("epubcfi(/6/2)", &spine()), Some(0));
+        assert_eq!(spine_position("epubcfi(/6/8!/4)", &spine()), None);
+        assert_eq!(spine_position("epubcfi(/6/3)", &spine()), None);
+    }
+
+    #[test]
+    fn spine_position_of_unknown_locations_is_none() {
+        for location in ["", "#only-a-fragment", "Text/appendix.xhtml", "42%"] {
+            assert_eq!(spine_position(location, &spine()), None, "{:?}", location);
+        }
+    }
+
+    fn user_version(conn: &Connection) -> i64 {
+        conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    fn table_exists(conn: &Connection, table: &str) -> bool {
+        conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?)",
+            params![table],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn migrations_upgrade_the_unversioned_baseline_schema() {
+        let conn = Connection::open_in_memory().unwrap();
+        register_functions(&conn).unwrap();
+        // The schema of the first release, before user_version was kept
+        conn.execute_batch(
+            "CREATE TABLE dictionary (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                word TEXT NOT NULL COLLATE NOCASE,
+                definition TEXT NOT NULL
+             );
+             CREATE INDEX idx_word ON dictionary(word COLLATE NOCASE);
+             INSERT INTO dictionary (word, definition) VALUES ('old', 'A seed row.');",
+        )
+        .unwrap();
+        assert_eq!(user_version(&conn), 0);
+
+        init_schema(&conn, None).unwrap();
+        assert_eq!(user_version(&conn), MIGRATIONS.len() as i64);
+        assert!(has_column(&conn, "dictionary", "ipa").unwrap());
+        assert!(has_column(&conn, "books", "deleted_at").unwrap());
+        assert!(has_column(&conn, "bookmarks", "note").unwrap());
+        assert!(table_exists(&conn, "reading_goals"));
+        // The legacy table only held seed data, so it is re-seeded from scratch
+        assert!(!search(&conn, "compiler").unwrap().results.is_empty());
+        assert!(search(&conn, "old").unwrap().results.is_empty());
+    }
+
+    #[test]
+    fn migrations_continue_from_a_mid_version() {
+        let conn = Connection::open_in_memory().unwrap();
+        register_functions(&conn).unwrap();
+        for migration in &MIGRATIONS[..10] {
+            migration(&conn).unwrap();
+        }
+        conn.pragma_update(None, "user_version", 10).unwrap();
+        conn.execute_batch(
+            "INSERT INTO books (id, title, file_path) VALUES (1, 'Done', 'done.epub'),
+                (2, 'Started', 'started.epub');
+             INSERT INTO reading_progress (book_id, location, percent, updated_at)
+                VALUES (1, 'end', 1.0, 1000), (2, 'start', 0.1, 2000);",
+        )
+        .unwrap();
+
+        run_migrations(&conn).unwrap();
+        assert_eq!(user_version(&conn), MIGRATIONS.len() as i64);
+        assert!(table_exists(&conn, "hash_cache"));
+        assert!(table_exists(&conn, "collections"));
+        let finished: Vec<Option<i64>> = conn
+            .prepare("SELECT finished_at FROM books ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(finished, [Some(1000), None]);
+
+        // Running again with nothing left to apply changes nothing
+        run_migrations(&conn).unwrap();
+        assert_eq!(user_version(&conn), MIGRATIONS.len() as i64);
+    }
+}
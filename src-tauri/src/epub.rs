@@ -0,0 +1,953 @@
+use roxmltree::{Document, Node, ParsingOptions};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tauri::{Emitter, Manager};
+use zip::ZipArchive;
+
+use crate::error::AppError;
+use crate::utils::{hash_file, CancelFlags, HashAlgorithm};
+
+#[derive(Serialize)]
+pub struct EpubMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub language: Option<String>,
+    /// Archive path of the cover image, if the package declares one
+    pub cover_href: Option<String>,
+}
+
+/// One navigation entry; `depth` is 0 for top-level chapters
+#[derive(Serialize)]
+pub struct TocEntry {
+    pub title: String,
+    /// Archive path of the target document, including any `#fragment`
+    pub href: String,
+    pub depth: usize,
+}
+
+/// A content document in reading order
+#[derive(Serialize)]
+pub struct SpineItem {
+    /// Manifest id of the document
+    pub id: String,
+    /// Archive path of the document
+    pub href: String,
+    pub media_type: String,
+}
+
+/// Raw bytes of a file inside an EPUB
+#[derive(Serialize)]
+pub struct EpubResource {
+    pub data: Vec<u8>,
+    pub media_type: String,
+}
+
+#[derive(Serialize)]
+pub struct WordCount {
+    pub word: String,
+    pub count: usize,
+}
+
+#[derive(Serialize)]
+pub struct ReadingTime {
+    pub words: usize,
+    pub minutes: u32,
+}
+
+/// Readable text of one spine document
+#[derive(Serialize)]
+pub struct ChapterText {
+    pub href: String,
+    pub text: String,
+}
+
+const OPS_NAMESPACE: &str = "http://www.idpf.org/2007/ops";
+const NCX_MEDIA_TYPE: &str = "application/x-dtbncx+xml";
+/// Words per minute assumed when the caller passes 0
+const DEFAULT_WPM: u32 = 200;
+/// Common English function words left out of `word_frequency`
+const STOPWORDS: &[&str] = &[
+    "a", "about", "after", "all", "also", "an", "and", "any", "are", "as", "at", "be", "been",
+    "but", "by", "can", "could", "did", "do", "does", "for", "from", "had", "has", "have", "he",
+    "her", "him", "his", "i", "if", "in", "into", "is", "it", "its", "it's", "me", "my", "no",
+    "not", "of", "on", "one", "or", "our", "out", "over", "she", "so", "some", "than", "that",
+    "the", "their", "them", "then", "there", "these", "they", "this", "those", "to", "up", "upon",
+    "us", "was", "we", "were", "what", "when", "which", "who", "will", "with", "would", "you",
+    "your",
+];
+
+/// An item from the OPF manifest; `href` is resolved to a path inside the archive
+struct ManifestItem {
+    id: String,
+    href: String,
+    media_type: String,
+    properties: String,
+}
+
+/// The parts of the OPF package document the commands need
+struct Package {
+    metadata: EpubMetadata,
+    manifest: Vec<ManifestItem>,
+    /// Manifest ids in reading order
+    spine: Vec<String>,
+    /// Manifest id of the EPUB2 NCX named by `<spine toc="...">`
+    spine_toc: Option<String>,
+}
+
+impl Package {
+    fn item(&self, id: &str) -> Option<&ManifestItem> {
+        self.manifest.iter().find(|item| item.id == id)
+    }
+
+    fn item_with_property(&self, property: &str) -> Option<&ManifestItem> {
+        self.manifest
+            .iter()
+            .find(|item| item.properties.split_whitespace().any(|p| p == property))
+    }
+
+    fn ncx_item(&self) -> Option<&ManifestItem> {
+        self.spine_toc
+            .as_deref()
+            .and_then(|id| self.item(id))
+            .or_else(|| {
+                self.manifest
+                    .iter()
+                    .find(|item| item.media_type == NCX_MEDIA_TYPE)
+            })
+    }
+}
+
+fn invalid_epub(reason: impl std::fmt::Display) -> AppError {
+    AppError::InvalidFormat(format!("Not a valid EPUB: {}", reason))
+}
+
+fn open_archive(path: &str) -> Result<ZipArchive<File>, AppError> {
+    let file = File::open(path)?;
+    ZipArchive::new(file).map_err(|e| invalid_epub(format!("not a zip archive: {}", e)))
+}
+
+fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> Result<String, AppError> {
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|_| invalid_epub(format!("missing {}", name)))?;
+    let mut content = String::new();
+    entry
+        .read_to_string(&mut content)
+        .map_err(|e| invalid_epub(format!("unreadable {}: {}", name, e)))?;
+    Ok(content)
+}
+
+/// Parse an XML document, allowing the `<!DOCTYPE>` most EPUB files carry
+fn parse_xml(content: &str) -> Result<Document<'_>, roxmltree::Error> {
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    Document::parse_with_options(content, options)
+}
+
+/// Find the first child element with the given local name, ignoring namespaces
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|n| n.is_element() && n.tag_name().name() == name)
+}
+
+fn element_text(node: Node) -> Option<String> {
+    let text: String = node
+        .descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect();
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Decode `%XX` escapes in an href
+fn percent_decode(href: &str) -> String {
+    let bytes = href.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Resolve an href relative to `base_dir` into a normalized archive path,
+/// dropping any `#fragment`
+fn resolve_href(base_dir: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let href = percent_decode(href);
+
+    let mut parts: Vec<&str> = if href.starts_with('/') {
+        Vec::new()
+    } else {
+        base_dir.split('/').filter(|p| !p.is_empty()).collect()
+    };
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// Like `resolve_href`, but keeps the `#fragment` so links can target a section
+fn resolve_link(base_dir: &str, href: &str) -> String {
+    match href.split_once('#') {
+        Some((path, fragment)) => format!("{}#{}", resolve_href(base_dir, path), fragment),
+        None => resolve_href(base_dir, href),
+    }
+}
+
+/// Directory portion of an archive path, without a trailing slash
+fn parent_dir(path: &str) -> &str {
+    path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("")
+}
+
+/// Locate the OPF package document via `META-INF/container.xml`
+fn find_opf_path(archive: &mut ZipArchive<File>) -> Result<String, AppError> {
+    let container = read_entry(archive, "META-INF/container.xml")?;
+    let doc = parse_xml(&container)
+        .map_err(|e| invalid_epub(format!("malformed container.xml: {}", e)))?;
+
+    doc.descendants()
+        .find(|n| n.is_element() && n.tag_name().name() == "rootfile")
+        .and_then(|n| n.attribute("full-path"))
+        .map(|path| path.to_string())
+        .ok_or_else(|| invalid_epub("container.xml has no rootfile"))
+}
+
+fn parse_package(archive: &mut ZipArchive<File>) -> Result<Package, AppError> {
+    let opf_path = find_opf_path(archive)?;
+    let opf = read_entry(archive, &opf_path)?;
+    let doc =
+        parse_xml(&opf).map_err(|e| invalid_epub(format!("malformed package document: {}", e)))?;
+    let root = doc.root_element();
+    let opf_dir = parent_dir(&opf_path);
+
+    let manifest: Vec<ManifestItem> = child(root, "manifest")
+        .map(|manifest| {
+            manifest
+                .children()
+                .filter(|n| n.is_element() && n.tag_name().name() == "item")
+                .filter_map(|item| {
+                    Some(ManifestItem {
+                        id: item.attribute("id")?.to_string(),
+                        href: resolve_href(opf_dir, item.attribute("href")?),
+                        media_type: item.attribute("media-type").unwrap_or("").to_string(),
+                        properties: item.attribute("properties").unwrap_or("").to_string(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let spine_node = child(root, "spine");
+    let spine = spine_node
+        .map(|spine| {
+            spine
+                .children()
+                .filter(|n| n.is_element() && n.tag_name().name() == "itemref")
+                .filter_map(|n| n.attribute("idref"))
+                .map(|idref| idref.to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    let spine_toc = spine_node
+        .and_then(|spine| spine.attribute("toc"))
+        .map(|id| id.to_string());
+
+    let metadata_node =
+        child(root, "metadata").ok_or_else(|| invalid_epub("package document has no metadata"))?;
+    let metadata_text = |name: &str| child(metadata_node, name).and_then(element_text);
+
+    let creators: Vec<String> = metadata_node
+        .children()
+        .filter(|n| n.is_element() && n.tag_name().name() == "creator")
+        .filter_map(element_text)
+        .collect();
+
+    let metadata = EpubMetadata {
+        title: metadata_text("title"),
+        author: (!creators.is_empty()).then(|| creators.join(", ")),
+        language: metadata_text("language"),
+        cover_href: find_cover_href(metadata_node, &manifest),
+    };
+
+    Ok(Package {
+        metadata,
+        manifest,
+        spine,
+        spine_toc,
+    })
+}
+
+/// EPUB3 marks the cover with `properties="cover-image"`; EPUB2 points at it
+/// with `<meta name="cover" content="item-id"/>`
+fn find_cover_href(metadata: Node, manifest: &[ManifestItem]) -> Option<String> {
+    if let Some(item) = manifest.iter().find(|item| {
+        item.properties
+            .split_whitespace()
+            .any(|p| p == "cover-image")
+    }) {
+        return Some(item.href.clone());
+    }
+
+    let cover_id = metadata
+        .children()
+        .filter(|n| n.is_element() && n.tag_name().name() == "meta")
+        .find(|n| n.attribute("name") == Some("cover"))
+        .and_then(|n| n.attribute("content"))?;
+
+    manifest
+        .iter()
+        .find(|item| item.id == cover_id)
+        .map(|item| item.href.clone())
+}
+
+/// Read the Dublin Core title, creator and language from an EPUB
+#[tauri::command]
+pub fn extract_epub_metadata(path: String) -> Result<EpubMetadata, AppError> {
+    let mut archive = open_archive(&path)?;
+    Ok(parse_package(&mut archive)?.metadata)
+}
+
+/// File extension for a cached cover, from its manifest media type or failing
+/// that its href
+fn cover_extension(media_type: &str, href: &str) -> String {
+    let from_type = match media_type {
+        "image/jpeg" => Some("jpg"),
+        "image/png" => Some("png"),
+        "image/gif" => Some("gif"),
+        "image/webp" => Some("webp"),
+        "image/svg+xml" => Some("svg"),
+        _ => None,
+    };
+    from_type
+        .map(str::to_string)
+        .or_else(|| {
+            Path::new(href)
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+        })
+        .unwrap_or_else(|| "img".to_string())
+}
+
+/// Write the cover of the EPUB at `path` to `covers_dir/<book hash>.<ext>`,
+/// reusing a file cached by an earlier call. `None` means the book has no cover.
+fn cache_cover(path: &str, covers_dir: &Path) -> Result<Option<PathBuf>, AppError> {
+    let hash = hash_file(Path::new(path), HashAlgorithm::Sha256)?;
+
+    // The extension isn't known until the package is parsed, so match on the stem
+    if let Ok(entries) = fs::read_dir(covers_dir) {
+        for entry in entries.flatten() {
+            let cached = entry.path();
+            if cached.file_stem().is_some_and(|stem| *stem == *hash) {
+                return Ok(Some(cached));
+            }
+        }
+    }
+
+    let mut archive = open_archive(path)?;
+    let package = parse_package(&mut archive)?;
+    let Some(cover_href) = package.metadata.cover_href else {
+        return Ok(None);
+    };
+    let media_type = package
+        .manifest
+        .iter()
+        .find(|item| item.href == cover_href)
+        .map(|item| item.media_type.as_str())
+        .unwrap_or_default();
+
+    // A cover the package declares but the archive lacks is treated as no cover
+    let Ok(mut entry) = archive.by_name(&cover_href) else {
+        return Ok(None);
+    };
+    let mut image = Vec::new();
+    entry
+        .read_to_end(&mut image)
+        .map_err(|e| invalid_epub(format!("unreadable cover {}: {}", cover_href, e)))?;
+
+    fs::create_dir_all(covers_dir)?;
+    let extension = cover_extension(media_type, &cover_href);
+    let cached = covers_dir.join(format!("{}.{}", hash, extension));
+    // Write under a temporary name so a crash never leaves a truncated cover
+    // cached; its stem isn't the bare hash, so the lookup above never matches it
+    let partial = cached.with_extension(format!("{}.partial", extension));
+    fs::write(&partial, &image)?;
+    fs::rename(&partial, &cached)?;
+
+    Ok(Some(cached))
+}
+
+/// Directory of cached cover images, named by the book file's SHA-256
+pub fn covers_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf, AppError> {
+    Ok(app_handle.path().app_data_dir()?.join("covers"))
+}
+
+/// Delete the cached cover of the book whose file hashes to `hash`, if any
+pub fn remove_cached_cover(covers_dir: &Path, hash: &str) -> Result<(), AppError> {
+    let Ok(entries) = fs::read_dir(covers_dir) else {
+        return Ok(());
+    };
+    for entry in entries.flatten() {
+        let cached = entry.path();
+        if cached.file_stem().is_some_and(|stem| *stem == *hash) {
+            fs::remove_file(&cached)?;
+        }
+    }
+    Ok(())
+}
+
+/// Extract an EPUB's cover image into the app's `covers` cache and return the
+/// cached file's path, or an empty string when the book has no cover
+#[tauri::command]
+pub fn extract_cover(path: String, app_handle: tauri::AppHandle) -> Result<String, AppError> {
+    Ok(cache_cover(&path, &covers_dir(&app_handle)?)?
+        .map(|cached| cached.to_string_lossy().into_owned())
+        .unwrap_or_default())
+}
+
+/// Walk the `<ol>` of an EPUB3 nav document, one level per nested list
+fn collect_nav_entries(list: Node, base_dir: &str, depth: usize, entries: &mut Vec<TocEntry>) {
+    for item in list
+        .children()
+        .filter(|n| n.is_element() && n.tag_name().name() == "li")
+    {
+        // A list item is either a link or a bare heading grouping its children
+        let label = child(item, "a").or_else(|| child(item, "span"));
+        if let Some(label) = label {
+            entries.push(TocEntry {
+                title: element_text(label).unwrap_or_default(),
+                href: label
+                    .attribute("href")
+                    .map(|href| resolve_link(base_dir, href))
+                    .unwrap_or_default(),
+                depth,
+            });
+        }
+        if let Some(sublist) = child(item, "ol") {
+            collect_nav_entries(sublist, base_dir, depth + 1, entries);
+        }
+    }
+}
+
+fn parse_nav_toc(content: &str, base_dir: &str) -> Vec<TocEntry> {
+    let Ok(doc) = parse_xml(content) else {
+        return Vec::new();
+    };
+
+    let navs: Vec<Node> = doc
+        .descendants()
+        .filter(|n| n.is_element() && n.tag_name().name() == "nav")
+        .collect();
+    let toc_nav = navs
+        .iter()
+        .find(|nav| {
+            nav.attribute((OPS_NAMESPACE, "type"))
+                .is_some_and(|kind| kind.split_whitespace().any(|k| k == "toc"))
+        })
+        .or(navs.first());
+
+    let mut entries = Vec::new();
+    if let Some(list) = toc_nav.and_then(|nav| child(*nav, "ol")) {
+        collect_nav_entries(list, base_dir, 0, &mut entries);
+    }
+    entries
+}
+
+/// Walk the nested `<navPoint>` elements of an EPUB2 NCX
+fn collect_ncx_entries(parent: Node, base_dir: &str, depth: usize, entries: &mut Vec<TocEntry>) {
+    for point in parent
+        .children()
+        .filter(|n| n.is_element() && n.tag_name().name() == "navPoint")
+    {
+        entries.push(TocEntry {
+            title: child(point, "navLabel")
+                .and_then(element_text)
+                .unwrap_or_default(),
+            href: child(point, "content")
+                .and_then(|content| content.attribute("src"))
+                .map(|src| resolve_link(base_dir, src))
+                .unwrap_or_default(),
+            depth,
+        });
+        collect_ncx_entries(point, base_dir, depth + 1, entries);
+    }
+}
+
+fn parse_ncx_toc(content: &str, base_dir: &str) -> Vec<TocEntry> {
+    let Ok(doc) = parse_xml(content) else {
+        return Vec::new();
+    };
+
+    let mut entries = Vec::new();
+    if let Some(nav_map) = child(doc.root_element(), "navMap") {
+        collect_ncx_entries(nav_map, base_dir, 0, &mut entries);
+    }
+    entries
+}
+
+/// Last resort when the book has no navigation document: one entry per spine item
+fn spine_toc(package: &Package) -> Vec<TocEntry> {
+    package
+        .spine
+        .iter()
+        .filter_map(|id| package.item(id))
+        .map(|item| TocEntry {
+            title: item
+                .href
+                .rsplit('/')
+                .next()
+                .and_then(|name| name.split('.').next())
+                .unwrap_or_default()
+                .to_string(),
+            href: item.href.clone(),
+            depth: 0,
+        })
+        .collect()
+}
+
+/// Read the table of contents from the EPUB3 nav document or the EPUB2 NCX.
+/// A malformed navigation document yields an empty list, and books without one
+/// fall back to the spine order.
+#[tauri::command]
+pub fn get_epub_toc(path: String) -> Result<Vec<TocEntry>, AppError> {
+    let mut archive = open_archive(&path)?;
+    let package = parse_package(&mut archive)?;
+
+    let (nav_href, is_nav) = match package.item_with_property("nav") {
+        Some(item) => (item.href.clone(), true),
+        None => match package.ncx_item() {
+            Some(item) => (item.href.clone(), false),
+            None => return Ok(spine_toc(&package)),
+        },
+    };
+
+    let Ok(content) = read_entry(&mut archive, &nav_href) else {
+        return Ok(Vec::new());
+    };
+    let base_dir = parent_dir(&nav_href);
+
+    Ok(if is_nav {
+        parse_nav_toc(&content, base_dir)
+    } else {
+        parse_ncx_toc(&content, base_dir)
+    })
+}
+
+/// List the content documents of the OPF `<spine>` in reading order, for paging
+/// through books whose navigation is broken or missing. Spine entries that don't
+/// name a manifest item are left out.
+#[tauri::command]
+pub fn get_epub_spine(path: String) -> Result<Vec<SpineItem>, AppError> {
+    let mut archive = open_archive(&path)?;
+    let package = parse_package(&mut archive)?;
+
+    Ok(package
+        .spine
+        .iter()
+        .filter_map(|id| package.item(id))
+        .map(|item| SpineItem {
+            id: item.id.clone(),
+            href: item.href.clone(),
+            media_type: item.media_type.clone(),
+        })
+        .collect())
+}
+
+/// Normalize an archive path requested by the frontend, refusing any that would
+/// climb out of the archive root
+fn archive_path(href: &str) -> Result<String, AppError> {
+    let href = href.split('#').next().unwrap_or_default();
+    let href = percent_decode(href).replace('\\', "/");
+
+    let mut parts = Vec::new();
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                if parts.pop().is_none() {
+                    return Err(AppError::InvalidInput(format!(
+                        "Resource path {} escapes the book",
+                        href
+                    )));
+                }
+            }
+            part => parts.push(part),
+        }
+    }
+    if parts.is_empty() {
+        return Err(AppError::InvalidInput("Resource path is empty".to_string()));
+    }
+    Ok(parts.join("/"))
+}
+
+/// Media type for a resource the manifest doesn't list, from its extension
+fn guess_media_type(href: &str) -> &'static str {
+    let extension = Path::new(href)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("xhtml") => "application/xhtml+xml",
+        Some("html" | "htm") => "text/html",
+        Some("css") => "text/css",
+        Some("xml" | "opf") => "application/xml",
+        Some("ncx") => NCX_MEDIA_TYPE,
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("ttf") => "font/ttf",
+        Some("otf") => "font/otf",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Read an image, stylesheet or other file from inside an EPUB by its archive
+/// path, e.g. to resolve an `<img src>` after joining it with the chapter's
+/// directory. Paths that climb above the archive root are rejected.
+#[tauri::command]
+pub fn read_epub_resource(path: String, href: String) -> Result<EpubResource, AppError> {
+    let href = archive_path(&href)?;
+    let mut archive = open_archive(&path)?;
+    let package = parse_package(&mut archive)?;
+    let media_type = package
+        .manifest
+        .iter()
+        .find(|item| item.href == href)
+        .map(|item| item.media_type.clone())
+        .filter(|media_type| !media_type.is_empty())
+        .unwrap_or_else(|| guess_media_type(&href).to_string());
+
+    let mut entry = archive
+        .by_name(&href)
+        .map_err(|_| AppError::NotFound(format!("{} is not in the book", href)))?;
+    let mut data = Vec::new();
+    entry
+        .read_to_end(&mut data)
+        .map_err(|e| invalid_epub(format!("unreadable {}: {}", href, e)))?;
+
+    Ok(EpubResource { data, media_type })
+}
+
+/// Elements whose boundaries should break the text into separate lines
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+/// ASCII case-insensitive `find`; lowercasing ASCII keeps byte offsets intact
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack.to_ascii_lowercase().find(needle)
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    if let Some(number) = entity.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+
+    Some(match entity {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "ndash" => '\u{2013}',
+        "mdash" => '\u{2014}',
+        "lsquo" => '\u{2018}',
+        "rsquo" => '\u{2019}',
+        "ldquo" => '\u{201c}',
+        "rdquo" => '\u{201d}',
+        "hellip" => '\u{2026}',
+        "copy" => '\u{a9}',
+        _ => return None,
+    })
+}
+
+/// Replace character references such as `&amp;` or `&#8217;`; unknown ones are kept as-is
+fn decode_entities(text: &str, out: &mut String) {
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let decoded = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((decode_entity(&rest[1..end + 1])?, end + 2)));
+        match decoded {
+            Some((ch, len)) => {
+                out.push(ch);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+}
+
+/// Reduce an (X)HTML document to readable text: tags, comments, scripts and
+/// styles are dropped, entities decoded, and block elements become line breaks
+pub fn strip_html(html: &str) -> String {
+    let mut raw = String::with_capacity(html.len());
+    let mut rest = match find_ignore_case(html, "<body") {
+        Some(start) => &html[start..],
+        None => html,
+    };
+
+    while let Some(lt) = rest.find('<') {
+        decode_entities(&rest[..lt], &mut raw);
+        rest = &rest[lt..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+
+        let Some(gt) = rest.find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[1..gt];
+        rest = &rest[gt + 1..];
+
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        let is_open = !tag.starts_with('/') && !tag.ends_with('/');
+        if is_open && (name == "script" || name == "style") {
+            rest = find_ignore_case(rest, &format!("</{}", name)).map_or("", |end| &rest[end..]);
+            continue;
+        }
+
+        if BLOCK_ELEMENTS.contains(&name.as_str()) {
+            raw.push('\n');
+        }
+    }
+    decode_entities(rest, &mut raw);
+
+    raw.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Extract the text of every (X)HTML document in the spine, in reading order
+pub fn extract_chapters(path: &str) -> Result<Vec<ChapterText>, AppError> {
+    extract_chapters_with(path, |_, _, _| Ok(()))
+}
+
+/// `extract_chapters`, calling `on_chapter` with each chapter's href, its
+/// 1-based position and the number of chapters after it has been read. An
+/// error from `on_chapter` stops the extraction.
+fn extract_chapters_with(
+    path: &str,
+    mut on_chapter: impl FnMut(&str, usize, usize) -> Result<(), AppError>,
+) -> Result<Vec<ChapterText>, AppError> {
+    let mut archive = open_archive(path)?;
+    let package = parse_package(&mut archive)?;
+
+    let documents: Vec<&ManifestItem> = package
+        .spine
+        .iter()
+        .filter_map(|id| package.item(id))
+        .filter(|item| {
+            matches!(
+                item.media_type.as_str(),
+                "application/xhtml+xml" | "text/html"
+            )
+        })
+        .collect();
+
+    let mut chapters = Vec::new();
+    for (i, item) in documents.iter().enumerate() {
+        // A missing chapter shouldn't make the rest of the book unreadable
+        if let Ok(content) = read_entry(&mut archive, &item.href) {
+            chapters.push(ChapterText {
+                href: item.href.clone(),
+                text: strip_html(&content),
+            });
+        }
+        on_chapter(&item.href, i + 1, documents.len())?;
+    }
+
+    Ok(chapters)
+}
+
+/// Running `extract_chapters_async` jobs, for `cancel_text_extract`
+#[derive(Default)]
+pub struct ExtractState(pub Arc<CancelFlags>);
+
+/// Payload of the `text-extract-progress` event
+#[derive(Clone, Serialize)]
+pub struct ExtractProgress {
+    pub job_id: String,
+    pub path: String,
+    pub href: String,
+    pub current: usize,
+    pub total: usize,
+}
+
+/// Extract the text of every chapter of an EPUB off the command thread,
+/// emitting `text-extract-progress` after each one. `job_id` is chosen by the
+/// caller and must be unique among running extractions; `cancel_text_extract`
+/// with the same id aborts this one with an error.
+#[tauri::command]
+pub async fn extract_chapters_async(
+    path: String,
+    job_id: String,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, ExtractState>,
+) -> Result<Vec<ChapterText>, AppError> {
+    let job = CancelFlags::start(&state.0, &job_id)?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        extract_chapters_with(&path, |href, current, total| {
+            if job.is_cancelled() {
+                return Err(AppError::Cancelled);
+            }
+            let _ = app_handle.emit(
+                "text-extract-progress",
+                ExtractProgress {
+                    job_id: job_id.clone(),
+                    path: path.clone(),
+                    href: href.to_string(),
+                    current,
+                    total,
+                },
+            );
+            Ok(())
+        })
+    })
+    .await?
+}
+
+/// Ask the running `extract_chapters_async` with `job_id` to stop, returning
+/// whether there was one
+#[tauri::command]
+pub fn cancel_text_extract(job_id: String, state: tauri::State<ExtractState>) -> bool {
+    state.0.cancel(&job_id)
+}
+
+/// Pull the readable text out of an EPUB, chapters separated by blank lines
+#[tauri::command]
+pub fn extract_plain_text(path: String) -> Result<String, AppError> {
+    let chapters = extract_chapters(&path)?;
+    Ok(chapters
+        .into_iter()
+        .map(|chapter| chapter.text)
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n"))
+}
+
+/// Count the words in an EPUB and estimate the minutes needed to read it at `wpm`
+/// words per minute (200 when 0 is passed)
+#[tauri::command]
+pub fn estimate_reading_time(path: String, wpm: u32) -> Result<ReadingTime, AppError> {
+    let wpm = if wpm == 0 { DEFAULT_WPM } else { wpm };
+    let words: usize = extract_chapters(&path)?
+        .iter()
+        .map(|chapter| chapter.text.split_whitespace().count())
+        .sum();
+
+    Ok(ReadingTime {
+        words,
+        minutes: words.div_ceil(wpm as usize) as u32,
+    })
+}
+
+/// Count how often each word appears in an EPUB and return the `top_n` most
+/// frequent, ignoring case, numbers and common stopwords
+#[tauri::command]
+pub fn word_frequency(path: String, top_n: usize) -> Result<Vec<WordCount>, AppError> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for chapter in extract_chapters(&path)? {
+        // Apostrophes are kept inside words so "don't" isn't split into "don" and "t"
+        for token in chapter
+            .text
+            .split(|c: char| !c.is_alphanumeric() && c != '\'' && c != '’')
+        {
+            let word = token
+                .trim_matches(|c| c == '\'' || c == '’')
+                .replace('’', "'")
+                .to_lowercase();
+            if word.chars().any(char::is_alphabetic) && !STOPWORDS.contains(&word.as_str()) {
+                *counts.entry(word).or_default() += 1;
+            }
+        }
+    }
+
+    let mut words: Vec<WordCount> = counts
+        .into_iter()
+        .map(|(word, count)| WordCount { word, count })
+        .collect();
+    words.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.word.cmp(&b.word)));
+    words.truncate(top_n);
+
+    Ok(words)
+}
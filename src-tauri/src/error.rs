@@ -0,0 +1,51 @@
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+/// Error returned by every command. It serializes as `{ code, message }` so the
+/// frontend can switch on `code` instead of parsing the message.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error("Database error: {0}")]
+    Db(#[from] rusqlite::Error),
+    #[error("Database pool error: {0}")]
+    Pool(#[from] r2d2::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    InvalidFormat(String),
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error("The document is password-protected")]
+    PasswordProtected,
+    #[error("Operation cancelled")]
+    Cancelled,
+    #[error("{0}")]
+    Tauri(#[from] tauri::Error),
+}
+
+impl AppError {
+    /// Stable identifier the frontend can match on
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Db(_) | Self::Pool(_) => "db",
+            Self::Io(_) => "io",
+            Self::NotFound(_) => "not_found",
+            Self::InvalidFormat(_) => "invalid_format",
+            Self::InvalidInput(_) => "invalid_input",
+            Self::PasswordProtected => "password_protected",
+            Self::Cancelled => "cancelled",
+            Self::Tauri(_) => "internal",
+        }
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("AppError", 2)?;
+        error.serialize_field("code", self.code())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.end()
+    }
+}
@@ -1,21 +1,176 @@
 mod db;
+mod epub;
+mod error;
+mod mobi;
+mod pdf;
+mod text;
+mod utils;
 
-use db::{init_db, search_dictionary, DbState};
-use tauri::Manager;
+use db::{
+    add_book, add_book_to_collection, add_bookmark, add_books_bulk, add_entry, add_favorite,
+    add_highlight, add_tag_to_book, all_progress, backfill_hashes, books_with_highlight_text,
+    browse_entries, cached_file_hash, chapter_progress, clear_all_personal_data, clear_favorites,
+    clear_lookup_history, create_collection, delete_book, delete_bookmark, delete_entry,
+    empty_trash, end_session, entry_counts, export_dictionary, export_favorites_anki,
+    export_highlights_markdown, export_library, find_missing_books, get_book, get_book_settings,
+    get_data_dir, get_pref_bool, get_pref_int, get_preference, get_progress, goal_progress,
+    highlight_color_stats, import_dictionary, import_from_calibre, import_library, index_all_books,
+    index_book, init_db, is_db_ready, library_disk_usage, list_all_tags, list_bookmarks,
+    list_books, list_books_by_tag, list_collection_tree, list_dictionary_sources, list_favorites,
+    list_finished, list_highlights, list_trash, lookup_in_context, merge_duplicate_words,
+    move_collection, optimize_database, random_word, reading_stats, recent_lookups,
+    recently_opened, relink_book, remove_favorite, remove_tag_from_book, reset_dictionary,
+    restore_book, save_book_settings, save_progress, search_books, search_dictionary,
+    search_dictionary_batch, search_highlights, search_library, session_summary, set_daily_goal,
+    set_finished, set_preference, start_session, suggest_words, touch_book, update_book_metadata,
+    update_bookmark, update_entry, verify_book, word_exists, word_of_the_day, DbReady, DbState,
+};
+use epub::{
+    cancel_text_extract, estimate_reading_time, extract_chapters_async, extract_cover,
+    extract_epub_metadata, extract_plain_text, get_epub_spine, get_epub_toc, read_epub_resource,
+    word_frequency, ExtractState,
+};
+use mobi::extract_mobi_metadata;
+use pdf::{pdf_extract_text, pdf_page_count};
+use std::sync::atomic::Ordering;
+use tauri::{Emitter, Manager};
+use text::{read_text_file, text_file_stats};
+use utils::{
+    cancel_hash, detect_format, files_identical, get_file_hash, hash_bytes, hash_directory,
+    hash_file_async, read_file_range, HashState,
+};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
+        .manage(HashState::default())
+        .manage(ExtractState::default())
+        .manage(DbReady::default())
         .setup(|app| {
             // Initialize database with app handle to access bundled resources
-            let conn =
+            let pool =
                 init_db(Some(app.handle())).expect("Failed to initialize dictionary database");
-            app.manage(DbState(std::sync::Mutex::new(conn)));
+            app.manage(DbState(pool));
+            // Lets the frontend hold off searching until the database can answer
+            app.state::<DbReady>().0.store(true, Ordering::SeqCst);
+            let _ = app.emit("db-ready", ());
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![search_dictionary])
+        .invoke_handler(tauri::generate_handler![
+            is_db_ready,
+            search_dictionary,
+            search_dictionary_batch,
+            word_exists,
+            suggest_words,
+            browse_entries,
+            word_of_the_day,
+            random_word,
+            entry_counts,
+            merge_duplicate_words,
+            reset_dictionary,
+            add_entry,
+            update_entry,
+            delete_entry,
+            list_dictionary_sources,
+            recent_lookups,
+            clear_lookup_history,
+            add_favorite,
+            remove_favorite,
+            list_favorites,
+            clear_favorites,
+            clear_all_personal_data,
+            export_favorites_anki,
+            reading_stats,
+            get_preference,
+            set_preference,
+            get_pref_bool,
+            get_pref_int,
+            import_dictionary,
+            export_dictionary,
+            add_book,
+            add_books_bulk,
+            import_from_calibre,
+            list_books,
+            get_book,
+            search_books,
+            verify_book,
+            find_missing_books,
+            relink_book,
+            cached_file_hash,
+            backfill_hashes,
+            optimize_database,
+            library_disk_usage,
+            touch_book,
+            update_book_metadata,
+            recently_opened,
+            set_finished,
+            list_finished,
+            delete_book,
+            restore_book,
+            list_trash,
+            empty_trash,
+            start_session,
+            end_session,
+            session_summary,
+            set_daily_goal,
+            goal_progress,
+            save_progress,
+            get_progress,
+            chapter_progress,
+            all_progress,
+            save_book_settings,
+            get_book_settings,
+            add_bookmark,
+            list_bookmarks,
+            update_bookmark,
+            delete_bookmark,
+            add_highlight,
+            list_highlights,
+            export_highlights_markdown,
+            search_highlights,
+            books_with_highlight_text,
+            highlight_color_stats,
+            index_book,
+            index_all_books,
+            search_library,
+            lookup_in_context,
+            add_tag_to_book,
+            remove_tag_from_book,
+            list_books_by_tag,
+            list_all_tags,
+            create_collection,
+            move_collection,
+            add_book_to_collection,
+            list_collection_tree,
+            export_library,
+            import_library,
+            get_data_dir,
+            get_file_hash,
+            hash_bytes,
+            files_identical,
+            hash_file_async,
+            cancel_hash,
+            hash_directory,
+            detect_format,
+            read_file_range,
+            extract_epub_metadata,
+            extract_cover,
+            get_epub_toc,
+            get_epub_spine,
+            read_epub_resource,
+            extract_plain_text,
+            extract_chapters_async,
+            cancel_text_extract,
+            estimate_reading_time,
+            word_frequency,
+            extract_mobi_metadata,
+            pdf_page_count,
+            pdf_extract_text,
+            read_text_file,
+            text_file_stats
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
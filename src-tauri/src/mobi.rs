@@ -0,0 +1,443 @@
+use encoding_rs::{UTF_8, WINDOWS_1252};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::error::AppError;
+
+/// Size of the Palm database header, after which the record list starts
+const PDB_HEADER_SIZE: usize = 78;
+/// Size of one entry in the record list: a 4-byte offset and 4 bytes of flags
+const PDB_RECORD_ENTRY_SIZE: usize = 8;
+/// The MOBI header follows the 16-byte PalmDOC header in record 0
+const MOBI_HEADER_OFFSET: usize = 16;
+/// Bit of the MOBI header's EXTH flags saying an EXTH block follows it
+const EXTH_PRESENT_FLAG: u32 = 0x40;
+/// `text_encoding` value of UTF-8 books; everything else is Windows-1252
+const UTF8_TEXT_ENCODING: u32 = 65001;
+/// MOBI header version used by KF8 (AZW3) books
+const KF8_FILE_VERSION: u32 = 8;
+
+/// EXTH record types this module reads
+const EXTH_AUTHOR: u32 = 100;
+const EXTH_PUBLISHER: u32 = 101;
+const EXTH_KF8_BOUNDARY: u32 = 121;
+const EXTH_UPDATED_TITLE: u32 = 503;
+const EXTH_LANGUAGE: u32 = 524;
+
+/// Which generation of Kindle format a file is
+#[derive(Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum MobiFormat {
+    /// Plain PalmDOC text without a MOBI header
+    PalmDoc,
+    /// An older MOBI book
+    Mobi,
+    /// A KF8 book, as sold in AZW3 files
+    Azw3,
+    /// A MOBI book carrying a KF8 version of itself, as built by kindlegen
+    Hybrid,
+}
+
+#[derive(Serialize)]
+pub struct MobiMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub publisher: Option<String>,
+    pub language: Option<String>,
+    pub format: MobiFormat,
+}
+
+fn invalid(message: &str) -> AppError {
+    AppError::InvalidFormat(format!("Not a valid MOBI file: {}", message))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Decode header text in the book's declared encoding, dropping padding NULs
+fn decode(bytes: &[u8], utf8: bool) -> Option<String> {
+    let encoding = if utf8 { UTF_8 } else { WINDOWS_1252 };
+    let (text, _) = encoding.decode_without_bom_handling(bytes);
+    let text = text.trim_matches(char::from(0)).trim();
+    Some(text.to_string()).filter(|text| !text.is_empty())
+}
+
+/// A Palm database, read one record at a time so large books never sit fully
+/// in memory
+struct PalmDatabase {
+    file: File,
+    name: Vec<u8>,
+    kind: [u8; 8],
+    offsets: Vec<u64>,
+    size: u64,
+}
+
+impl PalmDatabase {
+    fn open(path: &str) -> Result<Self, AppError> {
+        let mut file = File::open(path)?;
+        let size = file.metadata()?.len();
+
+        let mut header = [0u8; PDB_HEADER_SIZE];
+        file.read_exact(&mut header)
+            .map_err(|_| invalid("the file is too short for a Palm database header"))?;
+        let record_count = read_u16(&header, 76).unwrap_or(0) as usize;
+        if record_count == 0 {
+            return Err(invalid("the database has no records"));
+        }
+
+        let mut entries = vec![0u8; record_count * PDB_RECORD_ENTRY_SIZE];
+        file.read_exact(&mut entries)
+            .map_err(|_| invalid("the record list is truncated"))?;
+        let offsets = entries
+            .chunks(PDB_RECORD_ENTRY_SIZE)
+            .map(|entry| read_u32(entry, 0).unwrap_or(0) as u64)
+            .collect();
+
+        let name = header[..32]
+            .split(|&b| b == 0)
+            .next()
+            .unwrap_or(&[])
+            .to_vec();
+        let mut kind = [0u8; 8];
+        kind.copy_from_slice(&header[60..68]);
+
+        Ok(PalmDatabase {
+            file,
+            name,
+            kind,
+            offsets,
+            size,
+        })
+    }
+
+    /// The bytes of record `index`, which run up to the start of the next one
+    fn record(&mut self, index: usize) -> Result<Vec<u8>, AppError> {
+        let start = *self
+            .offsets
+            .get(index)
+            .ok_or_else(|| invalid(&format!("record {} does not exist", index)))?;
+        let end = self.offsets.get(index + 1).copied().unwrap_or(self.size);
+        if start > end || end > self.size {
+            return Err(invalid(&format!("record {} lies outside the file", index)));
+        }
+
+        let mut data = vec![0u8; (end - start) as usize];
+        self.file.seek(SeekFrom::Start(start))?;
+        self.file.read_exact(&mut data)?;
+        Ok(data)
+    }
+}
+
+/// What a MOBI header record says about the book
+struct MobiHeader {
+    version: u32,
+    full_name: Option<String>,
+    exth: Vec<(u32, Vec<u8>)>,
+    utf8: bool,
+}
+
+impl MobiHeader {
+    fn parse(record: &[u8]) -> Result<Self, AppError> {
+        if record.get(MOBI_HEADER_OFFSET..MOBI_HEADER_OFFSET + 4) != Some(b"MOBI") {
+            return Err(invalid("record 0 has no MOBI header"));
+        }
+        let field = |offset: usize| read_u32(record, MOBI_HEADER_OFFSET + offset);
+        let truncated = || invalid("the MOBI header is truncated");
+
+        let header_length = field(4).ok_or_else(truncated)? as usize;
+        let utf8 = field(12).ok_or_else(truncated)? == UTF8_TEXT_ENCODING;
+        let version = field(20).ok_or_else(truncated)?;
+
+        // Offsets of the full name are from the start of the record
+        let full_name = match (read_u32(record, 84), read_u32(record, 88)) {
+            (Some(offset), Some(length)) => record
+                .get(offset as usize..offset as usize + length as usize)
+                .and_then(|name| decode(name, utf8)),
+            _ => None,
+        };
+
+        let has_exth = field(112).is_some_and(|flags| flags & EXTH_PRESENT_FLAG != 0);
+        let exth = if has_exth {
+            parse_exth(record, MOBI_HEADER_OFFSET + header_length)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(MobiHeader {
+            version,
+            full_name,
+            exth,
+            utf8,
+        })
+    }
+
+    /// Every value of EXTH records of `kind`, decoded as text
+    fn exth_text(&self, kind: u32) -> Vec<String> {
+        self.exth
+            .iter()
+            .filter(|(record_kind, _)| *record_kind == kind)
+            .filter_map(|(_, data)| decode(data, self.utf8))
+            .collect()
+    }
+}
+
+/// The `(type, data)` records of the EXTH block starting at `start`
+fn parse_exth(record: &[u8], start: usize) -> Result<Vec<(u32, Vec<u8>)>, AppError> {
+    if record.get(start..start + 4) != Some(b"EXTH") {
+        return Err(invalid(
+            "the EXTH block is missing though the header flags one",
+        ));
+    }
+    let count =
+        read_u32(record, start + 8).ok_or_else(|| invalid("the EXTH block is truncated"))?;
+
+    let mut records = Vec::new();
+    let mut offset = start + 12;
+    for _ in 0..count {
+        let (Some(kind), Some(length)) = (read_u32(record, offset), read_u32(record, offset + 4))
+        else {
+            return Err(invalid("an EXTH record is truncated"));
+        };
+        // The length includes the 8-byte type and length fields
+        let data = (length as usize)
+            .checked_sub(8)
+            .and_then(|length| record.get(offset + 8..offset + 8 + length))
+            .ok_or_else(|| invalid("an EXTH record runs past the header"))?;
+        records.push((kind, data.to_vec()));
+        offset += length as usize;
+    }
+
+    Ok(records)
+}
+
+/// Read the title, author, publisher and language of a Kindle book from its
+/// MOBI and EXTH headers, telling older MOBI files apart from KF8 (AZW3) ones
+/// and hybrids carrying both. For hybrids the KF8 headers are preferred.
+#[tauri::command]
+pub fn extract_mobi_metadata(path: String) -> Result<MobiMetadata, AppError> {
+    let mut database = PalmDatabase::open(&path)?;
+    let pdb_name = decode(&database.name, false);
+
+    match &database.kind {
+        b"BOOKMOBI" => {}
+        b"TEXtREAd" => {
+            return Ok(MobiMetadata {
+                title: pdb_name,
+                author: None,
+                publisher: None,
+                language: None,
+                format: MobiFormat::PalmDoc,
+            })
+        }
+        _ => return Err(invalid("the Palm database is not a MOBI book")),
+    }
+
+    let mut header = MobiHeader::parse(&database.record(0)?)?;
+    let mut format = if header.version >= KF8_FILE_VERSION {
+        MobiFormat::Azw3
+    } else {
+        MobiFormat::Mobi
+    };
+
+    // A hybrid names the record holding its KF8 header; 0xFFFFFFFF means none
+    let boundary = header
+        .exth
+        .iter()
+        .find(|(kind, _)| *kind == EXTH_KF8_BOUNDARY)
+        .and_then(|(_, data)| read_u32(data, 0))
+        .filter(|&index| index != u32::MAX && index != 0);
+    if format == MobiFormat::Mobi {
+        if let Some(index) = boundary {
+            let kf8 = database
+                .record(index as usize)
+                .and_then(|record| MobiHeader::parse(&record));
+            if let Ok(kf8) = kf8 {
+                header = kf8;
+                format = MobiFormat::Hybrid;
+            }
+        }
+    }
+
+    let title = header
+        .exth_text(EXTH_UPDATED_TITLE)
+        .into_iter()
+        .next()
+        .or(header.full_name.clone())
+        .or(pdb_name);
+    let authors = header.exth_text(EXTH_AUTHOR);
+
+    Ok(MobiMetadata {
+        title,
+        author: Some(authors.join(" & ")).filter(|author| !author.is_empty()),
+        publisher: header.exth_text(EXTH_PUBLISHER).into_iter().next(),
+        language: header.exth_text(EXTH_LANGUAGE).into_iter().next(),
+        format,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Record 0 of a MOBI book: PalmDOC header, MOBI header of `version`, then
+    /// the EXTH block (if any records are given) and the full name
+    fn mobi_record(version: u32, exth: &[(u32, &[u8])], full_name: &str) -> Vec<u8> {
+        let header_length = 232;
+        let mut record = vec![0u8; MOBI_HEADER_OFFSET + header_length];
+        let put = |record: &mut Vec<u8>, offset: usize, value: u32| {
+            record[offset..offset + 4].copy_from_slice(&value.to_be_bytes())
+        };
+        record[16..20].copy_from_slice(b"MOBI");
+        put(&mut record, 20, header_length as u32);
+        put(&mut record, 28, UTF8_TEXT_ENCODING);
+        put(&mut record, 36, version);
+
+        if !exth.is_empty() {
+            put(&mut record, 128, EXTH_PRESENT_FLAG);
+            let mut block = Vec::new();
+            for (kind, data) in exth {
+                block.extend_from_slice(&kind.to_be_bytes());
+                block.extend_from_slice(&(data.len() as u32 + 8).to_be_bytes());
+                block.extend_from_slice(data);
+            }
+            record.extend_from_slice(b"EXTH");
+            record.extend_from_slice(&(block.len() as u32 + 12).to_be_bytes());
+            record.extend_from_slice(&(exth.len() as u32).to_be_bytes());
+            record.extend_from_slice(&block);
+        }
+
+        let name_offset = record.len() as u32;
+        put(&mut record, 84, name_offset);
+        put(&mut record, 88, full_name.len() as u32);
+        record.extend_from_slice(full_name.as_bytes());
+        record
+    }
+
+    /// Write a Palm database of `kind` holding `records` and read its metadata
+    fn metadata_of(
+        test: &str,
+        kind: &[u8; 8],
+        records: &[Vec<u8>],
+    ) -> Result<MobiMetadata, AppError> {
+        let mut data = vec![0u8; PDB_HEADER_SIZE];
+        data[..8].copy_from_slice(b"PDB Name");
+        data[60..68].copy_from_slice(kind);
+        data[76..78].copy_from_slice(&(records.len() as u16).to_be_bytes());
+        let mut offset = PDB_HEADER_SIZE + records.len() * PDB_RECORD_ENTRY_SIZE;
+        for record in records {
+            data.extend_from_slice(&(offset as u32).to_be_bytes());
+            data.extend_from_slice(&[0; 4]);
+            offset += record.len();
+        }
+        for record in records {
+            data.extend_from_slice(record);
+        }
+
+        let path = std::env::temp_dir().join(format!(
+            "open_read_mobi_{}_{}.mobi",
+            test,
+            std::process::id()
+        ));
+        fs::write(&path, data).unwrap();
+        let metadata = extract_mobi_metadata(path.to_string_lossy().into_owned());
+        fs::remove_file(&path).unwrap();
+        metadata
+    }
+
+    #[test]
+    fn palmdoc_is_titled_by_its_database_name() {
+        let metadata = metadata_of("palmdoc", b"TEXtREAd", &[vec![0; 16]]).unwrap();
+        assert_eq!(metadata.format, MobiFormat::PalmDoc);
+        assert_eq!(metadata.title.as_deref(), Some("PDB Name"));
+        assert_eq!(metadata.author, None);
+    }
+
+    #[test]
+    fn mobi_metadata_comes_from_exth_records() {
+        let record = mobi_record(
+            6,
+            &[
+                (EXTH_AUTHOR, b"Ann Author"),
+                (EXTH_AUTHOR, b"Bob Writer"),
+                (EXTH_PUBLISHER, b"Press"),
+                (EXTH_LANGUAGE, b"en"),
+                (EXTH_UPDATED_TITLE, b"Updated Title"),
+            ],
+            "Full Name",
+        );
+        let metadata = metadata_of("exth", b"BOOKMOBI", &[record]).unwrap();
+        assert_eq!(metadata.format, MobiFormat::Mobi);
+        assert_eq!(metadata.title.as_deref(), Some("Updated Title"));
+        assert_eq!(metadata.author.as_deref(), Some("Ann Author & Bob Writer"));
+        assert_eq!(metadata.publisher.as_deref(), Some("Press"));
+        assert_eq!(metadata.language.as_deref(), Some("en"));
+
+        let record = mobi_record(8, &[], "Plain KF8");
+        let metadata = metadata_of("kf8", b"BOOKMOBI", &[record]).unwrap();
+        assert_eq!(metadata.format, MobiFormat::Azw3);
+        assert_eq!(metadata.title.as_deref(), Some("Plain KF8"));
+    }
+
+    #[test]
+    fn hybrid_prefers_the_kf8_headers() {
+        let boundary = 2u32.to_be_bytes();
+        let records = [
+            mobi_record(
+                6,
+                &[(EXTH_KF8_BOUNDARY, &boundary), (EXTH_AUTHOR, b"Old Author")],
+                "Old Name",
+            ),
+            b"text".to_vec(),
+            mobi_record(8, &[(EXTH_AUTHOR, b"New Author")], "KF8 Name"),
+        ];
+        let metadata = metadata_of("hybrid", b"BOOKMOBI", &records).unwrap();
+        assert_eq!(metadata.format, MobiFormat::Hybrid);
+        assert_eq!(metadata.title.as_deref(), Some("KF8 Name"));
+        assert_eq!(metadata.author.as_deref(), Some("New Author"));
+    }
+
+    #[test]
+    fn truncated_headers_are_errors() {
+        let is_invalid = |result: Result<MobiMetadata, AppError>| {
+            matches!(result, Err(AppError::InvalidFormat(_)))
+        };
+
+        // An EXTH block claiming more records than it holds
+        let mut record = mobi_record(6, &[(EXTH_AUTHOR, b"Ann")], "");
+        let count = MOBI_HEADER_OFFSET + 232 + 8;
+        record[count..count + 4].copy_from_slice(&5u32.to_be_bytes());
+        record.truncate(record.len() - 2);
+        assert!(is_invalid(metadata_of(
+            "short_exth",
+            b"BOOKMOBI",
+            &[record]
+        )));
+
+        // An EXTH record whose length runs past the end of the header
+        let mut record = mobi_record(6, &[(EXTH_AUTHOR, b"Ann")], "");
+        let length = MOBI_HEADER_OFFSET + 232 + 16;
+        record[length..length + 4].copy_from_slice(&1000u32.to_be_bytes());
+        assert!(is_invalid(metadata_of("long_exth", b"BOOKMOBI", &[record])));
+
+        // The flag promises an EXTH block that isn't there
+        let mut record = mobi_record(6, &[], "");
+        record[128..132].copy_from_slice(&EXTH_PRESENT_FLAG.to_be_bytes());
+        assert!(is_invalid(metadata_of("no_exth", b"BOOKMOBI", &[record])));
+
+        assert!(is_invalid(metadata_of(
+            "no_mobi",
+            b"BOOKMOBI",
+            &[vec![0; 8]]
+        )));
+        assert!(is_invalid(metadata_of("no_records", b"BOOKMOBI", &[])));
+    }
+}
@@ -0,0 +1,107 @@
+use lopdf::Document;
+use serde::Serialize;
+use std::path::Path;
+
+use crate::epub::ChapterText;
+use crate::error::AppError;
+
+/// Cap on the decompressed content of a single page, so a tiny compressed stream
+/// can't expand into gigabytes while extracting text
+const MAX_PAGE_CONTENT_SIZE: usize = 64 * 1024 * 1024;
+
+/// Load a PDF, refusing documents that can't be decrypted without a password.
+/// PDFs encrypted with an empty user password are decrypted transparently.
+fn open_pdf(path: &str) -> Result<Document, AppError> {
+    let document = Document::load(Path::new(path)).map_err(|e| match e {
+        lopdf::Error::IO(e) => AppError::Io(e),
+        lopdf::Error::InvalidPassword => AppError::PasswordProtected,
+        e => AppError::InvalidFormat(format!("Not a valid PDF: {}", e)),
+    })?;
+
+    if document.is_encrypted() {
+        return Err(AppError::PasswordProtected);
+    }
+    Ok(document)
+}
+
+fn page_text(document: &Document, page: u32) -> Result<String, AppError> {
+    let text = document
+        .extract_text_with_limit(&[page], MAX_PAGE_CONTENT_SIZE)
+        .map_err(|e| {
+            AppError::InvalidFormat(format!("Could not read text on page {}: {}", page, e))
+        })?;
+    Ok(text.trim().to_string())
+}
+
+/// Text of the pages of a PDF that could be read
+pub struct PdfPages {
+    /// Pages with any text, with the 1-based page number as `href`
+    pub pages: Vec<ChapterText>,
+    /// How many pages had text that couldn't be decoded
+    pub skipped: usize,
+}
+
+/// Text of every page. Pages whose text can't be decoded are counted and
+/// skipped rather than failing the whole document.
+pub fn extract_pages(path: &str) -> Result<PdfPages, AppError> {
+    let document = open_pdf(path)?;
+    let mut pages = Vec::new();
+    let mut skipped = 0;
+    for page in document.get_pages().into_keys() {
+        match page_text(&document, page) {
+            Ok(text) if !text.is_empty() => pages.push(ChapterText {
+                href: page.to_string(),
+                text,
+            }),
+            Ok(_) => {}
+            Err(_) => skipped += 1,
+        }
+    }
+    Ok(PdfPages { pages, skipped })
+}
+
+/// Result of `pdf_extract_text`
+#[derive(Serialize)]
+pub struct PdfText {
+    pub text: String,
+    /// Pages left out because their text couldn't be decoded
+    pub skipped_pages: usize,
+}
+
+/// Number of pages in a PDF
+#[tauri::command]
+pub fn pdf_page_count(path: String) -> Result<usize, AppError> {
+    Ok(open_pdf(&path)?.get_pages().len())
+}
+
+/// Plain text of one 1-based `page`, or of the whole document with pages
+/// separated by blank lines when no page is given. A single page that can't be
+/// decoded is an error; in the whole document it is left out and counted.
+#[tauri::command]
+pub fn pdf_extract_text(path: String, page: Option<usize>) -> Result<PdfText, AppError> {
+    let Some(page) = page else {
+        let PdfPages { pages, skipped } = extract_pages(&path)?;
+        let text = pages
+            .into_iter()
+            .map(|page| page.text)
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        return Ok(PdfText {
+            text,
+            skipped_pages: skipped,
+        });
+    };
+
+    let document = open_pdf(&path)?;
+    let page_count = document.get_pages().len();
+    if page == 0 || page > page_count {
+        return Err(AppError::InvalidInput(format!(
+            "Page {} is out of range, the document has {} pages",
+            page, page_count
+        )));
+    }
+    Ok(PdfText {
+        text: page_text(&document, page as u32)?,
+        skipped_pages: 0,
+    })
+}
@@ -0,0 +1,236 @@
+use encoding_rs::{CoderResult, Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::Read;
+
+use crate::error::AppError;
+
+/// How much of a text file is read at a time when counting
+const TEXT_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Default, Serialize)]
+pub struct TextStats {
+    pub lines: u64,
+    pub words: u64,
+    pub characters: u64,
+}
+
+/// Guess a UTF-16 byte order from where the zero bytes fall. Text without a BOM
+/// that is mostly ASCII has a zero in every other byte.
+fn guess_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(4096) & !1];
+    if sample.is_empty() {
+        return None;
+    }
+    let pairs = sample.len() / 2;
+    let even = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd = sample
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|&&b| b == 0)
+        .count();
+    if odd * 2 > pairs && even * 10 < pairs {
+        Some(UTF_16LE)
+    } else if even * 2 > pairs && odd * 10 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// Decode a plain-text book, trying in turn the encoding named by its BOM, UTF-16
+/// when the zero bytes suggest it, UTF-8 and finally Windows-1252
+pub fn decode_text(bytes: &[u8]) -> Result<String, AppError> {
+    let mut candidates = Vec::new();
+    let mut body = bytes;
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        candidates.push(encoding);
+        body = &bytes[bom_length..];
+    }
+    // Zero bytes are valid UTF-8, so UTF-16 has to be tried first
+    candidates.extend(guess_utf16(body));
+    candidates.push(UTF_8);
+    candidates.push(WINDOWS_1252);
+
+    candidates
+        .into_iter()
+        .find_map(|encoding| {
+            encoding
+                .decode_without_bom_handling_and_without_replacement(body)
+                .map(|text| text.into_owned())
+        })
+        .ok_or_else(|| {
+            AppError::InvalidFormat("Could not decode the file in any known encoding".to_string())
+        })
+}
+
+/// Read a plain-text file into a string, detecting UTF-8, UTF-16 (with or
+/// without a BOM) and Windows-1252
+#[tauri::command]
+pub fn read_text_file(path: String) -> Result<String, AppError> {
+    decode_text(&fs::read(path)?)
+}
+
+/// Running totals for `text_file_stats`, fed one decoded chunk at a time
+#[derive(Default)]
+struct TextCounter {
+    stats: TextStats,
+    in_word: bool,
+    last: Option<char>,
+}
+
+impl TextCounter {
+    fn update(&mut self, text: &str) {
+        for c in text.chars() {
+            self.stats.characters += 1;
+            if c == '\n' {
+                self.stats.lines += 1;
+            }
+            if c.is_whitespace() {
+                self.in_word = false;
+            } else if !self.in_word {
+                self.in_word = true;
+                self.stats.words += 1;
+            }
+            self.last = Some(c);
+        }
+    }
+
+    fn finish(mut self) -> TextStats {
+        // A last line without a trailing newline still counts
+        if self.last.is_some_and(|c| c != '\n') {
+            self.stats.lines += 1;
+        }
+        self.stats
+    }
+}
+
+/// Count the lines, words and characters of a plain-text file without reading
+/// it all into memory. The encoding is picked from the first chunk the same way
+/// `read_text_file` does, except that bytes invalid in it count as one
+/// replacement character each rather than falling back to Windows-1252.
+#[tauri::command]
+pub fn text_file_stats(path: String) -> Result<TextStats, AppError> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0u8; TEXT_CHUNK_SIZE];
+    let mut counter = TextCounter::default();
+    let mut decoder = None;
+    let mut text = String::with_capacity(TEXT_CHUNK_SIZE * 2);
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        let last = read == 0;
+        let decoder = decoder.get_or_insert_with(|| {
+            let chunk = &buffer[..read];
+            let encoding = Encoding::for_bom(chunk)
+                .map(|(encoding, _)| encoding)
+                .or_else(|| guess_utf16(chunk))
+                .unwrap_or(UTF_8);
+            encoding.new_decoder_with_bom_removal()
+        });
+
+        let mut input = &buffer[..read];
+        loop {
+            let (result, consumed, _) = decoder.decode_to_string(input, &mut text, last);
+            counter.update(&text);
+            text.clear();
+            input = &input[consumed..];
+            if result == CoderResult::InputEmpty {
+                break;
+            }
+        }
+
+        if last {
+            break;
+        }
+    }
+
+    Ok(counter.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(text: &str, little_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| {
+                if little_endian {
+                    unit.to_le_bytes()
+                } else {
+                    unit.to_be_bytes()
+                }
+            })
+            .collect()
+    }
+
+    /// `text_file_stats` of a temporary file holding `bytes`
+    fn stats_of(test: &str, bytes: &[u8]) -> TextStats {
+        let path = std::env::temp_dir().join(format!(
+            "open_read_text_{}_{}.txt",
+            test,
+            std::process::id()
+        ));
+        fs::write(&path, bytes).unwrap();
+        let stats = text_file_stats(path.to_string_lossy().into_owned());
+        fs::remove_file(&path).unwrap();
+        stats.unwrap()
+    }
+
+    #[test]
+    fn decode_text_strips_a_utf8_bom() {
+        assert_eq!(decode_text(b"\xEF\xBB\xBFCaf\xC3\xA9").unwrap(), "Café");
+        assert_eq!(decode_text(b"Caf\xC3\xA9").unwrap(), "Café");
+    }
+
+    #[test]
+    fn decode_text_reads_utf16_with_or_without_a_bom() {
+        let text = "Plain text, mostly ASCII: naïve";
+        for little_endian in [true, false] {
+            let bytes = utf16(text, little_endian);
+            assert_eq!(decode_text(&bytes).unwrap(), text);
+
+            let bom: &[u8] = if little_endian {
+                b"\xFF\xFE"
+            } else {
+                b"\xFE\xFF"
+            };
+            assert_eq!(decode_text(&[bom, &bytes].concat()).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn decode_text_falls_back_to_windows_1252() {
+        assert_eq!(
+            decode_text(b"\x93Quoted\x94 \x96 caf\xE9").unwrap(),
+            "\u{201C}Quoted\u{201D} \u{2013} café"
+        );
+    }
+
+    #[test]
+    fn text_file_stats_counts_a_last_line_without_a_newline() {
+        let stats = stats_of("no_newline", b"one two\nthree");
+        assert_eq!((stats.lines, stats.words, stats.characters), (2, 3, 13));
+
+        let stats = stats_of("newline", b"one two\nthree\n");
+        assert_eq!((stats.lines, stats.words, stats.characters), (2, 3, 14));
+
+        let stats = stats_of("empty", b"");
+        assert_eq!((stats.lines, stats.words, stats.characters), (0, 0, 0));
+    }
+
+    #[test]
+    fn text_file_stats_decodes_across_chunks() {
+        // A two-byte character straddles the first chunk boundary
+        let mut bytes = vec![b'a'; TEXT_CHUNK_SIZE - 1];
+        bytes.extend_from_slice("é b\n".as_bytes());
+        let stats = stats_of("chunks", &bytes);
+        assert_eq!(stats.words, 2);
+        assert_eq!(stats.characters, TEXT_CHUNK_SIZE as u64 + 3);
+
+        let bytes = [b"\xFF\xFE".as_slice(), &utf16("un deux\ntrois", true)].concat();
+        let stats = stats_of("utf16", &bytes);
+        assert_eq!((stats.lines, stats.words, stats.characters), (2, 3, 13));
+    }
+}
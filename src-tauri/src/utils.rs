@@ -0,0 +1,713 @@
+use md5::Md5;
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::Emitter;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::error::AppError;
+
+/// Files are hashed in fixed-size chunks so large books never sit fully in memory
+const HASH_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Cancellation flags of running jobs, keyed by the id the caller gave each
+/// one, so cancelling a job leaves any others running alongside it alone
+#[derive(Default)]
+pub struct CancelFlags(Mutex<HashMap<String, Arc<AtomicBool>>>);
+
+/// A job registered with `CancelFlags`, removed from it again when dropped
+pub struct CancelGuard {
+    flags: Arc<CancelFlags>,
+    job_id: String,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelFlags {
+    /// Register the job `job_id`, refusing an id that a running job already has
+    pub fn start(flags: &Arc<Self>, job_id: &str) -> Result<CancelGuard, AppError> {
+        let mut jobs = flags.0.lock().unwrap_or_else(|e| e.into_inner());
+        if jobs.contains_key(job_id) {
+            return Err(AppError::InvalidInput(format!(
+                "A job with id '{}' is already running",
+                job_id
+            )));
+        }
+        let cancelled = Arc::new(AtomicBool::new(false));
+        jobs.insert(job_id.to_string(), cancelled.clone());
+
+        Ok(CancelGuard {
+            flags: flags.clone(),
+            job_id: job_id.to_string(),
+            cancelled,
+        })
+    }
+
+    /// Ask the job `job_id` to stop, returning whether one was running
+    pub fn cancel(&self, job_id: &str) -> bool {
+        let jobs = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.get(job_id)
+            .map(|cancelled| cancelled.store(true, Ordering::SeqCst))
+            .is_some()
+    }
+}
+
+impl CancelGuard {
+    /// Whether the job has been asked to stop
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        let mut jobs = self.flags.0.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.remove(&self.job_id);
+    }
+}
+
+/// Running `hash_file_async` jobs, for `cancel_hash`
+#[derive(Default)]
+pub struct HashState(pub Arc<CancelFlags>);
+
+/// Payload of the `hash-progress` event
+#[derive(Clone, Serialize)]
+pub struct HashProgress {
+    pub job_id: String,
+    pub path: String,
+    pub bytes_read: u64,
+    pub total_bytes: u64,
+}
+
+/// How many leading bytes `detect_format` inspects
+const FORMAT_SNIFF_SIZE: u64 = 1024;
+
+/// Book formats recognized from file contents
+#[derive(Serialize, PartialEq, Eq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum FileFormat {
+    Epub,
+    Pdf,
+    Mobi,
+    PlainText,
+    Unknown,
+}
+
+/// Digest algorithms accepted by the hashing commands
+#[derive(Clone, Copy)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha1,
+    Md5,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Parse an algorithm name, treating an empty name as SHA-256
+    pub fn from_name(name: &str) -> Result<Self, AppError> {
+        match name.trim().to_lowercase().as_str() {
+            "" | "sha256" => Ok(Self::Sha256),
+            "sha1" => Ok(Self::Sha1),
+            "md5" => Ok(Self::Md5),
+            "blake3" => Ok(Self::Blake3),
+            other => Err(AppError::InvalidInput(format!(
+                "Unknown hash algorithm '{}', expected sha256, sha1, md5 or blake3",
+                other
+            ))),
+        }
+    }
+
+    /// The canonical name `from_name` accepts for this algorithm
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha1 => "sha1",
+            Self::Md5 => "md5",
+            Self::Blake3 => "blake3",
+        }
+    }
+}
+
+/// Incremental hasher over whichever algorithm was selected
+enum Hasher {
+    Sha256(Sha256),
+    Sha1(Sha1),
+    Md5(Md5),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+            HashAlgorithm::Sha1 => Self::Sha1(Sha1::new()),
+            HashAlgorithm::Md5 => Self::Md5(Md5::new()),
+            HashAlgorithm::Blake3 => Self::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(hasher) => hasher.update(data),
+            Self::Sha1(hasher) => hasher.update(data),
+            Self::Md5(hasher) => hasher.update(data),
+            Self::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    /// Consume the hasher and return the lowercase hex digest
+    fn finalize_hex(self) -> String {
+        match self {
+            Self::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            Self::Sha1(hasher) => format!("{:x}", hasher.finalize()),
+            Self::Md5(hasher) => format!("{:x}", hasher.finalize()),
+            Self::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+/// Feed `reader` through the hasher chunk by chunk, reporting the running byte
+/// count to `on_chunk`, which can abort hashing by returning an error
+fn hash_stream<R: Read>(
+    mut reader: R,
+    algorithm: HashAlgorithm,
+    mut on_chunk: impl FnMut(u64) -> Result<(), AppError>,
+) -> Result<String, AppError> {
+    let mut hasher = Hasher::new(algorithm);
+    let mut buffer = vec![0u8; HASH_CHUNK_SIZE];
+    let mut bytes_read = 0u64;
+
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        bytes_read += read as u64;
+        on_chunk(bytes_read)?;
+    }
+
+    Ok(hasher.finalize_hex())
+}
+
+/// Hash a file on disk, returning the lowercase hex digest
+pub fn hash_file(path: &Path, algorithm: HashAlgorithm) -> Result<String, AppError> {
+    let file = File::open(path)?;
+    hash_stream(file, algorithm, |_| Ok(()))
+}
+
+/// Compute the digest of a file as a lowercase hex string.
+/// `algorithm` is one of `sha256` (the default when empty), `sha1`, `md5` or `blake3`.
+#[tauri::command]
+pub fn get_file_hash(path: String, algorithm: String) -> Result<String, AppError> {
+    let algorithm = HashAlgorithm::from_name(&algorithm)?;
+    hash_file(Path::new(&path), algorithm)
+}
+
+/// Compute the digest of in-memory data, such as pasted text, as a lowercase hex
+/// string. Takes the same `algorithm` names as `get_file_hash` and produces the
+/// same digest a file with these contents would.
+#[tauri::command]
+pub fn hash_bytes(data: Vec<u8>, algorithm: String) -> Result<String, AppError> {
+    let algorithm = HashAlgorithm::from_name(&algorithm)?;
+    hash_stream(data.as_slice(), algorithm, |_| Ok(()))
+}
+
+/// Whether two files have the same contents, comparing sizes before hashing so
+/// files of different lengths are never read. A missing file is an error.
+#[tauri::command]
+pub fn files_identical(
+    path_a: String,
+    path_b: String,
+    algorithm: String,
+) -> Result<bool, AppError> {
+    let algorithm = HashAlgorithm::from_name(&algorithm)?;
+    let (path_a, path_b) = (Path::new(&path_a), Path::new(&path_b));
+    if fs::metadata(path_a)?.len() != fs::metadata(path_b)?.len() {
+        return Ok(false);
+    }
+    Ok(hash_file(path_a, algorithm)? == hash_file(path_b, algorithm)?)
+}
+
+/// Hash a file off the command thread, emitting `hash-progress` after every chunk.
+/// `job_id` is chosen by the caller and must be unique among running hashes;
+/// `cancel_hash` with the same id aborts this hash with an error.
+#[tauri::command]
+pub async fn hash_file_async(
+    path: String,
+    algorithm: String,
+    job_id: String,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, HashState>,
+) -> Result<String, AppError> {
+    let algorithm = HashAlgorithm::from_name(&algorithm)?;
+    let job = CancelFlags::start(&state.0, &job_id)?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let file = File::open(&path)?;
+        let total_bytes = file.metadata()?.len();
+
+        hash_stream(file, algorithm, |bytes_read| {
+            if job.is_cancelled() {
+                return Err(AppError::Cancelled);
+            }
+            let _ = app_handle.emit(
+                "hash-progress",
+                HashProgress {
+                    job_id: job_id.clone(),
+                    path: path.clone(),
+                    bytes_read,
+                    total_bytes,
+                },
+            );
+            Ok(())
+        })
+    })
+    .await?
+}
+
+/// Ask the running `hash_file_async` with `job_id` to stop, returning whether
+/// there was one
+#[tauri::command]
+pub fn cancel_hash(job_id: String, state: tauri::State<HashState>) -> bool {
+    state.0.cancel(&job_id)
+}
+
+/// Result of `hash_directory`: digests for every file that could be read and the
+/// error message for every file or subdirectory that couldn't
+#[derive(Serialize, Default)]
+pub struct DirectoryHashes {
+    pub hashes: HashMap<String, String>,
+    pub errors: HashMap<String, String>,
+}
+
+/// Walk `dir` hashing each regular file. Symlinks are skipped rather than followed
+/// so a link back up the tree can't loop forever.
+fn walk_and_hash(
+    dir: &Path,
+    algorithm: HashAlgorithm,
+    recursive: bool,
+) -> Result<DirectoryHashes, AppError> {
+    let mut result = DirectoryHashes::default();
+    // The directory itself must be readable, failures below it are only recorded
+    let mut pending = vec![(dir.to_path_buf(), fs::read_dir(dir)?)];
+
+    while let Some((current, entries)) = pending.pop() {
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    result
+                        .errors
+                        .insert(current.to_string_lossy().into_owned(), e.to_string());
+                    continue;
+                }
+            };
+            let path = entry.path();
+            let key = path.to_string_lossy().into_owned();
+
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(e) => {
+                    result.errors.insert(key, e.to_string());
+                    continue;
+                }
+            };
+
+            if file_type.is_file() {
+                match hash_file(&path, algorithm) {
+                    Ok(hash) => {
+                        result.hashes.insert(key, hash);
+                    }
+                    Err(e) => {
+                        result.errors.insert(key, e.to_string());
+                    }
+                }
+            } else if file_type.is_dir() && recursive {
+                match fs::read_dir(&path) {
+                    Ok(entries) => pending.push((path, entries)),
+                    Err(e) => {
+                        result.errors.insert(key, e.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Hash every regular file in a directory, descending into subdirectories when
+/// `recursive` is set. Keys are the files' full paths. Unreadable entries are
+/// reported in `errors` instead of failing the whole walk.
+#[tauri::command]
+pub async fn hash_directory(
+    dir: String,
+    algorithm: String,
+    recursive: bool,
+) -> Result<DirectoryHashes, AppError> {
+    let algorithm = HashAlgorithm::from_name(&algorithm)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        walk_and_hash(Path::new(&dir), algorithm, recursive)
+    })
+    .await?
+}
+
+/// A zip is only an EPUB if it carries the EPUB `mimetype` entry (usually stored
+/// first, right after the local header) or an OCF `container.xml`
+fn is_epub_zip(header: &[u8], path: &Path) -> bool {
+    if header.get(30..58) == Some(b"mimetypeapplication/epub+zip".as_slice()) {
+        return true;
+    }
+    File::open(path)
+        .ok()
+        .and_then(|file| zip::ZipArchive::new(file).ok())
+        .is_some_and(|mut archive| archive.by_name("META-INF/container.xml").is_ok())
+}
+
+/// PDF if `%PDF-` opens the file, after at most a UTF-8 BOM and whitespace.
+/// Searching further in would take any text that quotes the marker for a PDF.
+fn is_pdf(header: &[u8]) -> bool {
+    let header = header.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(header);
+    let start = header
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(header.len());
+    header[start..].starts_with(b"%PDF-")
+}
+
+/// Text if it starts with a Unicode BOM or has no binary control bytes
+fn looks_like_text(header: &[u8]) -> bool {
+    if header.starts_with(&[0xEF, 0xBB, 0xBF])
+        || header.starts_with(&[0xFF, 0xFE])
+        || header.starts_with(&[0xFE, 0xFF])
+    {
+        return true;
+    }
+    !header
+        .iter()
+        .any(|&b| b < 0x09 || (0x0E..0x20).contains(&b) && b != 0x1B)
+}
+
+/// Identify a book's format from its leading bytes rather than its extension
+pub fn sniff_format(path: &Path) -> Result<FileFormat, AppError> {
+    let mut header = Vec::new();
+    File::open(path)?
+        .take(FORMAT_SNIFF_SIZE)
+        .read_to_end(&mut header)?;
+
+    let format = if header.is_empty() {
+        FileFormat::Unknown
+    } else if header.starts_with(b"PK\x03\x04") {
+        if is_epub_zip(&header, path) {
+            FileFormat::Epub
+        } else {
+            FileFormat::Unknown
+        }
+    } else if is_pdf(&header) {
+        FileFormat::Pdf
+    } else if matches!(header.get(60..68), Some(b"BOOKMOBI") | Some(b"TEXtREAd")) {
+        // Palm database type/creator at offset 60
+        FileFormat::Mobi
+    } else if looks_like_text(&header) {
+        FileFormat::PlainText
+    } else {
+        FileFormat::Unknown
+    };
+
+    Ok(format)
+}
+
+/// Detect whether a file is an EPUB, PDF, MOBI or plain text from its contents.
+/// Unrecognized content is reported as `unknown` rather than an error.
+#[tauri::command]
+pub fn detect_format(path: String) -> Result<FileFormat, AppError> {
+    sniff_format(Path::new(&path))
+}
+
+/// Read up to `length` bytes of a file starting at byte `offset`, for previewing
+/// or parsing part of a large book. Fewer bytes come back near the end of the
+/// file and none at all when `offset` is past it.
+#[tauri::command]
+pub fn read_file_range(path: String, offset: u64, length: usize) -> Result<Vec<u8>, AppError> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    if offset >= size {
+        return Ok(Vec::new());
+    }
+
+    file.seek(SeekFrom::Start(offset))?;
+    // Sized from the file so a huge `length` doesn't allocate up front
+    let mut data = Vec::with_capacity(length.min((size - offset) as usize));
+    file.take(length as u64).read_to_end(&mut data)?;
+
+    Ok(data)
+}
+
+/// Number of single-character insertions, deletions or substitutions needed to
+/// turn `a` into `b`
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // Only the previous row of the edit-distance table is needed
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+/// Lowercase `text` and strip its diacritics, so "Café" and "cafe" compare equal.
+/// Matches what the dictionary's `remove_diacritics` tokenizer does to words.
+pub fn fold_diacritics(text: &str) -> String {
+    text.nfd()
+        .filter(|&c| !is_combining_mark(c))
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Irregular inflections the suffix rules in `lemmas` can't undo
+const IRREGULAR_FORMS: &[(&str, &str)] = &[
+    ("am", "be"),
+    ("are", "be"),
+    ("ate", "eat"),
+    ("bought", "buy"),
+    ("began", "begin"),
+    ("been", "be"),
+    ("best", "good"),
+    ("better", "good"),
+    ("broke", "break"),
+    ("broken", "break"),
+    ("brought", "bring"),
+    ("built", "build"),
+    ("came", "come"),
+    ("caught", "catch"),
+    ("children", "child"),
+    ("chose", "choose"),
+    ("chosen", "choose"),
+    ("did", "do"),
+    ("does", "do"),
+    ("done", "do"),
+    ("drove", "drive"),
+    ("driven", "drive"),
+    ("eaten", "eat"),
+    ("fell", "fall"),
+    ("felt", "feel"),
+    ("feet", "foot"),
+    ("flew", "fly"),
+    ("found", "find"),
+    ("gave", "give"),
+    ("given", "give"),
+    ("geese", "goose"),
+    ("gone", "go"),
+    ("got", "get"),
+    ("grew", "grow"),
+    ("had", "have"),
+    ("has", "have"),
+    ("held", "hold"),
+    ("is", "be"),
+    ("kept", "keep"),
+    ("knew", "know"),
+    ("known", "know"),
+    ("left", "leave"),
+    ("lost", "lose"),
+    ("made", "make"),
+    ("meant", "mean"),
+    ("men", "man"),
+    ("met", "meet"),
+    ("mice", "mouse"),
+    ("paid", "pay"),
+    ("people", "person"),
+    ("ran", "run"),
+    ("said", "say"),
+    ("sat", "sit"),
+    ("saw", "see"),
+    ("seen", "see"),
+    ("sent", "send"),
+    ("spoke", "speak"),
+    ("spoken", "speak"),
+    ("spent", "spend"),
+    ("stood", "stand"),
+    ("taken", "take"),
+    ("taught", "teach"),
+    ("teeth", "tooth"),
+    ("thought", "think"),
+    ("threw", "throw"),
+    ("thrown", "throw"),
+    ("told", "tell"),
+    ("took", "take"),
+    ("was", "be"),
+    ("went", "go"),
+    ("were", "be"),
+    ("women", "woman"),
+    ("worse", "bad"),
+    ("worst", "bad"),
+    ("wore", "wear"),
+    ("worn", "wear"),
+    ("wrote", "write"),
+    ("written", "write"),
+];
+
+/// Stems shorter than this are too ambiguous to be worth looking up
+const MIN_STEM_LENGTH: usize = 2;
+
+/// Likely dictionary forms of an inflected English word, most plausible first,
+/// such as "run" for "running" or "study" for "studies". The rules only undo
+/// common suffixes, so some candidates aren't real words; callers keep the
+/// first one that is.
+pub fn lemmas(word: &str) -> Vec<String> {
+    let word = word.trim().to_lowercase();
+    let mut candidates = Vec::new();
+
+    if let Some(&(_, lemma)) = IRREGULAR_FORMS.iter().find(|(form, _)| *form == word) {
+        candidates.push(lemma.to_string());
+    }
+
+    // Undo one suffix, offering the bare stem, the stem with a silent e restored
+    // and the stem with a doubled final consonant removed
+    let mut push_stem = |stem: &str, with_e: bool| {
+        if stem.chars().count() < MIN_STEM_LENGTH {
+            return;
+        }
+        candidates.push(stem.to_string());
+        if with_e {
+            candidates.push(format!("{}e", stem));
+        }
+        let mut chars = stem.chars().rev();
+        if let (Some(last), Some(before)) = (chars.next(), chars.next()) {
+            if last == before && !"aeiouls".contains(last) {
+                candidates.push(stem[..stem.len() - last.len_utf8()].to_string());
+            }
+        }
+    };
+
+    if let Some(stem) = word
+        .strip_suffix("ies")
+        .or_else(|| word.strip_suffix("ied"))
+    {
+        push_stem(&format!("{}y", stem), false);
+    } else if let Some(stem) = word.strip_suffix("ves") {
+        push_stem(&format!("{}f", stem), true);
+    } else if let Some(stem) = word.strip_suffix("es") {
+        push_stem(stem, true);
+    } else if let Some(stem) = word.strip_suffix('s') {
+        if !stem.ends_with('s') && !stem.ends_with('u') {
+            push_stem(stem, false);
+        }
+    }
+
+    if let Some(stem) = word.strip_suffix("ying") {
+        push_stem(&format!("{}ie", stem), false);
+    }
+    if let Some(stem) = word.strip_suffix("ing") {
+        push_stem(stem, true);
+    }
+    if let Some(stem) = word.strip_suffix("ed") {
+        push_stem(stem, true);
+    }
+
+    if let Some(stem) = word
+        .strip_suffix("iest")
+        .or_else(|| word.strip_suffix("ier"))
+    {
+        push_stem(&format!("{}y", stem), false);
+    } else if let Some(stem) = word.strip_suffix("est").or_else(|| word.strip_suffix("er")) {
+        push_stem(stem, true);
+    }
+
+    if let Some(stem) = word.strip_suffix("ily") {
+        push_stem(&format!("{}y", stem), false);
+    } else if let Some(stem) = word.strip_suffix("ly") {
+        push_stem(stem, false);
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    candidates.retain(|candidate| *candidate != word && seen.insert(candidate.clone()));
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_file_streams_to_the_one_shot_digest() {
+        // Not a multiple of the chunk size, so the last read is a short one
+        let data: Vec<u8> = (0..5 * 1024 * 1024 + 123)
+            .map(|i: u32| (i % 251) as u8)
+            .collect();
+        let path = std::env::temp_dir().join(format!("open_read_hash_{}.bin", std::process::id()));
+        fs::write(&path, &data).unwrap();
+
+        let expected = [
+            (
+                HashAlgorithm::Sha256,
+                format!("{:x}", Sha256::digest(&data)),
+            ),
+            (HashAlgorithm::Sha1, format!("{:x}", Sha1::digest(&data))),
+            (HashAlgorithm::Md5, format!("{:x}", Md5::digest(&data))),
+            (
+                HashAlgorithm::Blake3,
+                blake3::hash(&data).to_hex().to_string(),
+            ),
+        ];
+        let results: Vec<_> = expected
+            .iter()
+            .map(|(algorithm, _)| hash_file(&path, *algorithm))
+            .collect();
+        fs::remove_file(&path).unwrap();
+
+        for ((algorithm, digest), result) in expected.iter().zip(results) {
+            assert_eq!(
+                &result.unwrap(),
+                digest,
+                "{} digest differs",
+                algorithm.name()
+            );
+        }
+    }
+
+    #[test]
+    fn is_pdf_needs_the_marker_at_the_start() {
+        assert!(is_pdf(b"%PDF-1.7\n"));
+        assert!(is_pdf(b"\r\n  %PDF-1.4"));
+        assert!(is_pdf(b"\xEF\xBB\xBF%PDF-1.4"));
+        assert!(!is_pdf(b"Notes on the %PDF- header"));
+        assert!(!is_pdf(b"junk%PDF-1.4"));
+        assert!(!is_pdf(b""));
+    }
+
+    #[test]
+    fn cancel_flags_are_kept_per_job() {
+        let flags = Arc::new(CancelFlags::default());
+        let first = CancelFlags::start(&flags, "first").unwrap();
+        let second = CancelFlags::start(&flags, "second").unwrap();
+        assert!(CancelFlags::start(&flags, "first").is_err());
+
+        assert!(flags.cancel("first"));
+        assert!(first.is_cancelled());
+        assert!(!second.is_cancelled());
+
+        // A finished job's id is free again, and starts out not cancelled
+        drop(first);
+        assert!(!flags.cancel("first"));
+        let again = CancelFlags::start(&flags, "first").unwrap();
+        assert!(!again.is_cancelled());
+        assert!(!second.is_cancelled());
+    }
+}
@@ -67,9 +67,11 @@ export const DictionaryBubble: React.FC<DictionaryBubbleProps> = ({ word, positi
                 try {
                     const { invoke } = await import('@tauri-apps/api/core');
                     if (typeof invoke === 'function') {
-                        const results: string[] = await invoke('search_dictionary', { word: cleanWord });
+                        const { results }: { results: { word: string; definition: string; part_of_speech: string | null; ipa: string | null }[]; suggestions: string[] } = await invoke('search_dictionary', { word: cleanWord });
                         if (results && results.length > 0) {
-                            setDefinitions(results);
+                            setDefinitions(results.map((entry) =>
+                                entry.part_of_speech ? `(${entry.part_of_speech}) ${entry.definition}` : entry.definition
+                            ));
                             setSource('local');
                             foundLocal = true;
                         }